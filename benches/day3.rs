@@ -0,0 +1,81 @@
+// Column counting for day 3 on large synthetic diagnostic dumps: the original per-bit loop
+// over u64 reports against the bit-sliced counter that replaced it, single and multi-threaded.
+//
+//     cargo bench --bench day3
+
+use rs_aoc2021::day3::{Day3, Report};
+use rs_aoc2021::rng::Rng;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Reports of the given width with pseudo-random bits, the same every run.
+fn reports(count: usize, width: usize) -> Vec<Report> {
+    let mut rng = Rng::new(0x2021_0003);
+    let mut reports = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut report = Report::new(width);
+        for i in 0..width {
+            report.set(i, rng.next_u64() >> 63 == 1);
+        }
+        reports.push(report);
+    }
+
+    reports
+}
+
+// What ones() replaced: the original gamma() loop over reports packed into u64s, rightmost
+// bit first, keeping a +1/-1 balance per bit.  Turned into counts of ones to check against.
+fn ones_by_balance(reports: &[u64], width: usize) -> Vec<usize> {
+    let mut balance: Vec<isize> = vec![0; width];
+
+    for report in reports {
+        for (position, bal) in balance.iter_mut().enumerate() {
+            let mask = 1 << position;
+            if (report & mask) > 0 {
+                *bal += 1;
+            } else {
+                *bal -= 1;
+            }
+        }
+    }
+
+    balance.iter().rev().map(|b| ((reports.len() as isize + b) / 2) as usize).collect()
+}
+
+// Best of a few runs, checking every run gets the expected counts.
+fn time<F: Fn() -> Vec<usize>>(expected: &[usize], f: F) -> Duration {
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            let ones = f();
+            let elapsed = start.elapsed();
+            assert_eq!(ones, expected);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let cores = thread::available_parallelism().map_or(4, |n| n.get());
+
+    // The old loop only took reports up to 64 bits wide.
+    for (count, width) in [(1_000_000, 12), (4_000_000, 12), (1_000_000, 64)] {
+        let reports = reports(count, width);
+        let values: Vec<u64> = reports.iter().map(|r| r.value().unwrap()).collect();
+        let mut day = Day3::from_reports(reports);
+        let expected = ones_by_balance(&values, width);
+
+        let by_bit = time(&expected, || ones_by_balance(&values, width));
+        let sliced = time(&expected, || day.ones());
+        day.set_threads(cores);
+        let threaded = time(&expected, || day.ones());
+
+        println!("{} reports x {} bits:", count, width);
+        println!("    per bit            {:>10.2?}", by_bit);
+        println!("    bit-sliced         {:>10.2?}  ({:.1}x)",
+                 sliced, by_bit.as_secs_f64() / sliced.as_secs_f64());
+        println!("    bit-sliced, {:>2} thr {:>10.2?}  ({:.1}x)",
+                 cores, threaded, by_bit.as_secs_f64() / threaded.as_secs_f64());
+    }
+}
@@ -0,0 +1,29 @@
+fn main() {
+    #[cfg(feature = "capi")]
+    generate_header();
+}
+
+// Generates the C header for src/capi.rs into OUT_DIR.  include/rs_aoc2021.h is a checked in
+// copy, which tests/capi.rs checks is up to date; build with UPDATE_CAPI_HEADER=1 to rewrite it.
+#[cfg(feature = "capi")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let header = format!("{}/rs_aoc2021.h", std::env::var("OUT_DIR").unwrap());
+
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=UPDATE_CAPI_HEADER");
+    println!("cargo:rustc-env=CAPI_HEADER={}", header);
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).unwrap();
+    cbindgen::Builder::new()
+        .with_src(format!("{}/src/capi.rs", crate_dir))
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C bindings")
+        .write_to_file(&header);
+
+    if std::env::var_os("UPDATE_CAPI_HEADER").is_some() {
+        std::fs::copy(&header, format!("{}/include/rs_aoc2021.h", crate_dir)).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+#ifndef RS_AOC2021_H
+#define RS_AOC2021_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Call succeeded.
+ */
+#define AOC_OK 0
+
+/**
+ * Call failed, see the error string.
+ */
+#define AOC_ERROR -1
+
+/**
+ * A loaded puzzle day.  Opaque to C.
+ */
+typedef struct AocDay AocDay;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Loads puzzle `day` (1-based) from an input buffer of `len` bytes.
+ *
+ * Returns NULL on failure and, if `error` is not NULL, stores a message in `*error`
+ * which must be released with aoc_string_free().
+ *
+ * # Safety
+ *
+ * `input` must point to `len` readable bytes, and `error` must be NULL or point to
+ * writable storage for one pointer.
+ */
+struct AocDay *aoc_day_new(uint32_t day, const uint8_t *input, size_t len, char **error);
+
+/**
+ * Releases a day returned by aoc_day_new().  Passing NULL is a no-op.
+ *
+ * # Safety
+ *
+ * `day` must be NULL or a pointer from aoc_day_new() that hasn't been freed yet.
+ */
+void aoc_day_free(struct AocDay *day);
+
+/**
+ * Solves `part` (1 or 2) of a loaded day.
+ *
+ * Returns AOC_OK and stores the answer in `*answer`, or returns AOC_ERROR and, if
+ * `error` is not NULL, stores a message in `*error` which must be released with
+ * aoc_string_free().
+ *
+ * # Safety
+ *
+ * `day` must be a live pointer from aoc_day_new(), `answer` must point to writable
+ * storage for a uint64_t, and `error` must be NULL or point to writable storage for
+ * one pointer.
+ */
+int aoc_day_solve(const struct AocDay *day, uint32_t part, uint64_t *answer, char **error);
+
+/**
+ * Releases an error string handed out by this library.  Passing NULL is a no-op.
+ *
+ * # Safety
+ *
+ * `s` must be NULL or a string from this library that hasn't been freed yet.
+ */
+void aoc_string_free(char *s);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RS_AOC2021_H */
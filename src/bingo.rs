@@ -0,0 +1,764 @@
+// Live bingo over TCP: day 4's calls and cards, played by people (or bots) on the network.
+//
+// The protocol is one line per message.  Clients send:
+//
+//     JOIN name      take a seat, answered with CARD
+//     CLAIM          claim a win on the calls so far, answered with WIN to everyone or REJECT
+//     QUIT
+//
+// and the server sends:
+//
+//     CARD 5x5 22 13 17 ...      the player's card, rows x columns then the numbers row by row
+//     CALL round number
+//     WIN round score name       an accepted claim
+//     REJECT reason              a claim that isn't a win
+//     ERROR reason               a line the server can't act on
+//     END                        the game is over
+//
+// The game starts once enough players have joined, and ends when the calls run out or every
+// player has won or left.
+
+use crate::day4::{BingoCard, Day4, WinRules};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the server checks for new connections while waiting.
+const POLL: Duration = Duration::from_millis(10);
+// How long a write to a player may block before the player is dropped, so one that stops
+// reading can't hold up the game for everyone else.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+// Longest line the server reads from a player, and the client from the server.  Cards are
+// the only long messages.
+const MAX_REQUEST: usize = 1024;
+const MAX_MESSAGE: usize = 1 << 20;
+// Most connections waiting to join at once.  Each has a thread reading it.
+const MAX_WAITING: usize = 64;
+// EMFILE, ENFILE and ENOMEM, numbered the same on every Unix: out of file descriptors or
+// memory for the moment, which players leaving can fix.
+#[cfg(unix)]
+const SHORT_OF_RESOURCES: [i32; 3] = [12, 23, 24];
+#[cfg(not(unix))]
+const SHORT_OF_RESOURCES: [i32; 0] = [];
+
+// What clients send.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    Join(String),
+    Claim,
+    Quit,
+}
+
+// What the server sends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Card { rows: usize, cols: usize, numbers: Vec<usize> },
+    Call { round: usize, number: usize },
+    Win { round: usize, score: usize, player: String },
+    Reject(String),
+    Error(String),
+    End,
+}
+
+// First word of a line and the rest of it.
+fn split_word(line: &str) -> (&str, &str) {
+    match line.trim().split_once(' ') {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (line.trim(), ""),
+    }
+}
+
+fn number(word: Option<&str>) -> Result<usize, String> {
+    let word = word.ok_or_else(|| String::from("missing number"))?;
+    word.parse::<usize>().map_err(|_| format!("bad number '{}'", word))
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Request, String> {
+        match split_word(line) {
+            ("JOIN", "") => Err(String::from("JOIN needs a name")),
+            ("JOIN", name) => Ok(Request::Join(name.to_string())),
+            ("CLAIM", "") => Ok(Request::Claim),
+            ("QUIT", "") => Ok(Request::Quit),
+            _ => Err(format!("unknown request '{}'", line.trim())),
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Request::Join(name) => write!(f, "JOIN {}", name),
+            Request::Claim => write!(f, "CLAIM"),
+            Request::Quit => write!(f, "QUIT"),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Message, String> {
+        let (word, rest) = split_word(line);
+        let mut words = rest.split_whitespace();
+        let message = match word {
+            "CARD" => {
+                let shape = words.next().unwrap_or("");
+                let (rows, cols) = shape.split_once('x')
+                    .and_then(|(r, c)| Some((r.parse::<usize>().ok()?, c.parse::<usize>().ok()?)))
+                    .ok_or_else(|| format!("bad card shape '{}'", shape))?;
+                let numbers = words.map(|w| number(Some(w))).collect::<Result<Vec<_>, _>>()?;
+                if numbers.len() != rows * cols {
+                    return Err(format!("{}x{} card with {} numbers", rows, cols, numbers.len()));
+                }
+                Message::Card { rows, cols, numbers }
+            }
+            "CALL" => Message::Call { round: number(words.next())?, number: number(words.next())? },
+            "WIN" => {
+                let (round, rest) = split_word(rest);
+                let (score, player) = split_word(rest);
+                if player.is_empty() {
+                    return Err(String::from("WIN needs a player"));
+                }
+                Message::Win {
+                    round: number(Some(round))?,
+                    score: number(Some(score))?,
+                    player: player.to_string(),
+                }
+            }
+            "REJECT" => Message::Reject(rest.to_string()),
+            "ERROR" => Message::Error(rest.to_string()),
+            "END" => Message::End,
+            _ => return Err(format!("unknown message '{}'", line.trim())),
+        };
+
+        Ok(message)
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Card { rows, cols, numbers } => {
+                write!(f, "CARD {}x{}", rows, cols)?;
+                for number in numbers {
+                    write!(f, " {}", number)?;
+                }
+                Ok(())
+            }
+            Message::Call { round, number } => write!(f, "CALL {} {}", round, number),
+            Message::Win { round, score, player } => {
+                write!(f, "WIN {} {} {}", round, score, player)
+            }
+            Message::Reject(reason) => write!(f, "REJECT {}", reason),
+            Message::Error(reason) => write!(f, "ERROR {}", reason),
+            Message::End => write!(f, "END"),
+        }
+    }
+}
+
+// An accepted claim.  Cards are numbered from 0, in the order they're dealt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Winner {
+    pub player: String,
+    pub card: usize,
+    pub round: usize,
+    pub score: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    // Players to wait for before the first call.
+    pub players: usize,
+    // Time between calls.
+    pub interval: Duration,
+    // How long a connection may go without joining before it's dropped.
+    pub join_timeout: Duration,
+}
+
+// From a connection's reader thread.
+enum Event {
+    Line(usize, String),
+    // A line the thread gave up on reading, and why.
+    Broken(usize, String),
+    Gone(usize),
+}
+
+struct Player {
+    stream: TcpStream,
+    connected: Instant,
+    name: Option<String>,
+    card: Option<usize>,
+    won: bool,
+    gone: bool,
+}
+
+// Everything the server knows about a game in progress.
+struct Game {
+    cards: Vec<BingoCard>,
+    players: Vec<Player>,
+    called: Vec<usize>,
+    started: bool,
+    winners: Vec<Winner>,
+    join_timeout: Duration,
+}
+
+impl Game {
+    fn seated(&self) -> usize {
+        self.players.iter().filter(|p| p.card.is_some()).count()
+    }
+
+    // Every player has won or left.
+    fn over(&self) -> bool {
+        self.players.iter().filter(|p| p.card.is_some()).all(|p| p.won || p.gone)
+    }
+
+    fn send(&mut self, id: usize, message: &Message) {
+        // In one write, so the message goes out as one packet.
+        let line = format!("{}\n", message);
+        let player = &mut self.players[id];
+        if !player.gone && player.stream.write_all(line.as_bytes()).is_err() {
+            self.leave(id);
+        }
+    }
+
+    fn broadcast(&mut self, message: &Message) {
+        for id in 0..self.players.len() {
+            if self.players[id].card.is_some() {
+                self.send(id, message);
+            }
+        }
+    }
+
+    // Connections still to join.
+    fn waiting(&self) -> impl Iterator<Item = usize> + '_ {
+        self.players.iter().enumerate()
+            .filter(|(_, p)| p.name.is_none() && !p.gone)
+            .map(|(id, _)| id)
+    }
+
+    // Drops connections that haven't joined in time, or at all once the game has started.
+    fn drop_waiting(&mut self) {
+        let late: Vec<usize> = self.waiting()
+            .filter(|id| self.started || self.players[*id].connected.elapsed() > self.join_timeout)
+            .collect();
+        let reason = if self.started { "game already started" } else { "took too long to join" };
+        for id in late {
+            self.send(id, &Message::Error(String::from(reason)));
+            self.leave(id);
+        }
+    }
+
+    // Takes a new connection, reading its lines on a thread of its own.
+    fn connect(&mut self, stream: TcpStream, events: &Sender<Event>) -> io::Result<()> {
+        // Calls are small and need to go out straight away, not wait on Nagle's algorithm.
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        let turned_away = if self.started {
+            Some("game already started")
+        } else if self.waiting().count() >= MAX_WAITING {
+            Some("too many connections waiting to join")
+        } else {
+            None
+        };
+        if let Some(reason) = turned_away {
+            // Not worth failing the game over.
+            let _ = writeln!(writer, "{}", Message::Error(String::from(reason)));
+            return Ok(());
+        }
+
+        let id = self.players.len();
+        let player = Player {
+            stream: writer,
+            connected: Instant::now(),
+            name: None,
+            card: None,
+            won: false,
+            gone: false,
+        };
+        self.players.push(player);
+        let events = events.clone();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            loop {
+                let event = match read_line(&mut reader, &mut line, MAX_REQUEST) {
+                    Ok(true) => Event::Line(id, line.clone()),
+                    Ok(false) => break,
+                    Err(e) => Event::Broken(id, e.to_string()),
+                };
+                let broken = matches!(event, Event::Broken(..));
+                if events.send(event).is_err() || broken {
+                    break;
+                }
+            }
+            let _ = events.send(Event::Gone(id));
+        });
+
+        Ok(())
+    }
+
+    fn handle(&mut self, event: Event) {
+        let (id, line) = match event {
+            Event::Line(id, line) => (id, line),
+            Event::Broken(id, msg) => {
+                self.send(id, &Message::Error(msg));
+                return self.leave(id);
+            }
+            Event::Gone(id) => return self.leave(id),
+        };
+
+        match line.parse::<Request>() {
+            Ok(Request::Join(_)) if self.players[id].name.is_some() => {
+                self.send(id, &Message::Error(String::from("already joined")));
+            }
+            Ok(Request::Join(_)) if self.started => {
+                self.send(id, &Message::Error(String::from("game already started")));
+            }
+            Ok(Request::Join(_)) if self.seated() == self.cards.len() => {
+                self.send(id, &Message::Error(String::from("no cards left")));
+            }
+            Ok(Request::Join(name)) => {
+                let card_no = self.seated();
+                let card = &self.cards[card_no];
+                let (rows, cols) = card.shape();
+                let message = Message::Card { rows, cols, numbers: card.numbers().to_vec() };
+                let player = &mut self.players[id];
+                player.name = Some(name);
+                player.card = Some(card_no);
+                self.send(id, &message);
+            }
+            Ok(Request::Claim) => self.claim(id),
+            Ok(Request::Quit) => self.leave(id),
+            Err(msg) => self.send(id, &Message::Error(msg)),
+        }
+    }
+
+    // Checks a claim against the calls so far.  A late claim still wins in the round the card
+    // was complete.
+    fn claim(&mut self, id: usize) {
+        let player = &self.players[id];
+        let (name, card) = match (&player.name, player.card) {
+            (Some(name), Some(card)) => (name.clone(), card),
+            _ => return self.send(id, &Message::Error(String::from("join first"))),
+        };
+        if player.won {
+            return self.send(id, &Message::Reject(String::from("already won")));
+        }
+
+        match self.cards[card].play(&self.called) {
+            Some((round, score)) => {
+                self.players[id].won = true;
+                self.winners.push(Winner { player: name.clone(), card, round, score });
+                self.broadcast(&Message::Win { round, score, player: name });
+            }
+            None => self.send(id, &Message::Reject(String::from("no winning line"))),
+        }
+    }
+
+    fn leave(&mut self, id: usize) {
+        let player = &mut self.players[id];
+        player.gone = true;
+        let _ = player.stream.shutdown(Shutdown::Both);
+    }
+
+    // Takes any new connections, then handles events for up to `wait`.  Only fails if the
+    // listener does in a way that won't pass; trouble with one connection just drops it.
+    fn poll(&mut self, listener: &TcpListener, events: &Sender<Event>, inbox: &Receiver<Event>,
+            wait: Duration) -> io::Result<()> {
+        loop {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(e) = self.connect(stream, events) {
+                        eprintln!("bingo: dropped connection from {}: {}", addr, e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if accept_can_pass(&e) => {
+                    eprintln!("bingo: couldn't accept a connection: {}", e);
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.drop_waiting();
+        if let Ok(event) = inbox.recv_timeout(wait) {
+            self.handle(event);
+        }
+        while let Ok(event) = inbox.try_recv() {
+            self.handle(event);
+        }
+
+        Ok(())
+    }
+}
+
+// Whether an accept() error is down to the one connection, or to running short of something
+// for now, rather than the listener being unusable.
+fn accept_can_pass(e: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    matches!(e.kind(), ConnectionAborted | ConnectionReset | Interrupted | TimedOut | OutOfMemory)
+        || e.raw_os_error().is_some_and(|code| SHORT_OF_RESOURCES.contains(&code))
+}
+
+pub struct Server {
+    listener: TcpListener,
+    game: Day4,
+    config: ServerConfig,
+}
+
+impl Server {
+    // Listens on addr (port 0 picks a free one) for a game of `game`'s calls, dealing its cards
+    // in order.
+    pub fn bind<A: ToSocketAddrs>(addr: A, game: Day4, config: ServerConfig) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server { listener, game, config })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Plays the game through, returning the accepted claims in the order they came in.
+    pub fn run(self) -> io::Result<Vec<Winner>> {
+        let Day4 { calls, cards } = self.game;
+        let mut game = Game {
+            cards,
+            players: Vec::new(),
+            called: Vec::new(),
+            started: false,
+            winners: Vec::new(),
+            join_timeout: self.config.join_timeout,
+        };
+        let (events, inbox) = mpsc::channel();
+
+        while game.seated() < self.config.players.min(game.cards.len()) {
+            game.poll(&self.listener, &events, &inbox, POLL)?;
+        }
+        game.started = true;
+        game.drop_waiting();
+
+        for (n, number) in calls.iter().enumerate() {
+            if game.over() {
+                break;
+            }
+            game.called.push(*number);
+            game.broadcast(&Message::Call { round: n + 1, number: *number });
+
+            let next = Instant::now() + self.config.interval;
+            while !game.over() {
+                let now = Instant::now();
+                if now >= next {
+                    break;
+                }
+                game.poll(&self.listener, &events, &inbox, (next - now).min(POLL))?;
+            }
+        }
+
+        game.broadcast(&Message::End);
+        for id in 0..game.players.len() {
+            game.leave(id);
+        }
+
+        Ok(game.winners)
+    }
+}
+
+// A player.  Card numbers come from the server, the rules they're played by don't, so the
+// client is told them to know when to claim.
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    card: BingoCard,
+    calls: Vec<usize>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Reads a line of at most max bytes into line, without its line ending.  Returns false at
+// the end of input, and fails on longer lines rather than buffer them.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String, max: usize) -> io::Result<bool> {
+    line.clear();
+    if reader.by_ref().take(max as u64 + 1).read_line(line)? == 0 {
+        return Ok(false);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    } else if line.len() > max {
+        return Err(invalid(format!("line longer than {} bytes", max)));
+    }
+
+    Ok(true)
+}
+
+// The next message from the server, None once it's hung up.
+fn read_message(reader: &mut BufReader<TcpStream>) -> io::Result<Option<Message>> {
+    let mut line = String::new();
+    if !read_line(reader, &mut line, MAX_MESSAGE)? {
+        return Ok(None);
+    }
+    line.parse::<Message>().map(Some).map_err(invalid)
+}
+
+impl Client {
+    // Connects and joins as `name`, waiting for a card.
+    pub fn join<A: ToSocketAddrs>(addr: A, name: &str, rules: &WinRules) -> io::Result<Client> {
+        let mut writer = TcpStream::connect(addr)?;
+        writer.set_nodelay(true)?;
+        let mut reader = BufReader::new(writer.try_clone()?);
+        writeln!(writer, "{}", Request::Join(name.to_string()))?;
+
+        let card = match read_message(&mut reader)? {
+            Some(Message::Card { cols, numbers, .. }) => {
+                let rows = numbers.chunks(cols.max(1)).map(|row| row.to_vec()).collect();
+                BingoCard::new(rows, rules).map_err(invalid)?
+            }
+            Some(Message::Error(msg)) => return Err(io::Error::other(msg)),
+            other => return Err(invalid(format!("expected a card, got {:?}", other))),
+        };
+
+        Ok(Client { reader, writer, card, calls: Vec::new() })
+    }
+
+    pub fn card(&self) -> &BingoCard {
+        &self.card
+    }
+
+    // Numbers called so far.
+    pub fn calls(&self) -> &[usize] {
+        &self.calls
+    }
+
+    pub fn send(&mut self, request: &Request) -> io::Result<()> {
+        writeln!(self.writer, "{}", request)
+    }
+
+    // The next message from the server, None once it's hung up.
+    pub fn receive(&mut self) -> io::Result<Option<Message>> {
+        let message = read_message(&mut self.reader)?;
+        if let Some(Message::Call { number, .. }) = message {
+            self.calls.push(number);
+        }
+
+        Ok(message)
+    }
+
+    // Plays automatically, claiming as soon as the card wins, until the game ends.  Every
+    // message from after joining is passed to `heard` as it arrives.
+    pub fn play_live<F: FnMut(&Message)>(mut self, mut heard: F) -> io::Result<()> {
+        let mut claimed = false;
+        while let Some(message) = self.receive()? {
+            // Only a number on the card can complete a line.
+            if let Message::Call { number, .. } = message {
+                let marked = self.card.position(number).is_some();
+                if !claimed && marked && self.card.play(&self.calls).is_some() {
+                    self.send(&Request::Claim)?;
+                    claimed = true;
+                }
+            }
+            heard(&message);
+            if message == Message::End {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Like play_live(), returning every message at the end.
+    pub fn play(self) -> io::Result<Vec<Message>> {
+        let mut messages = Vec::new();
+        self.play_live(|message| messages.push(message.clone()))?;
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(players: usize, interval_ms: u64) -> ServerConfig {
+        ServerConfig {
+            players,
+            interval: Duration::from_millis(interval_ms),
+            join_timeout: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_protocol() {
+        let messages = [
+            Message::Card { rows: 2, cols: 2, numbers: vec![1, 2, 3, 4] },
+            Message::Call { round: 3, number: 17 },
+            Message::Win { round: 12, score: 4512, player: String::from("Ann Lee") },
+            Message::Reject(String::from("no winning line")),
+            Message::End,
+        ];
+        for message in messages.iter() {
+            assert_eq!(message.to_string().parse::<Message>().as_ref(), Ok(message));
+        }
+        assert_eq!(messages[2].to_string(), "WIN 12 4512 Ann Lee");
+        assert_eq!("CARD 2x2 1 2 3".parse::<Message>(),
+                   Err(String::from("2x2 card with 3 numbers")));
+        assert_eq!("CALL 1 x".parse::<Message>(), Err(String::from("bad number 'x'")));
+
+        assert_eq!(" JOIN  Bo \r".parse::<Request>(), Ok(Request::Join(String::from("Bo"))));
+        assert_eq!("CLAIM".parse::<Request>(), Ok(Request::Claim));
+        assert_eq!("JOIN".parse::<Request>(), Err(String::from("JOIN needs a name")));
+        assert_eq!("BINGO!".parse::<Request>(), Err(String::from("unknown request 'BINGO!'")));
+    }
+
+    #[test]
+    fn test_game() {
+        let d = Day4::load("data/day4_example1.txt");
+        let expected: Vec<(usize, usize)> = d.cards().iter()
+            .map(|card| card.play(d.calls()).unwrap())
+            .collect();
+        let server = Server::bind("127.0.0.1:0", d, config(3, 50)).unwrap();
+        let addr = server.local_addr().unwrap();
+        let running = thread::spawn(move || server.run());
+
+        // Joining one at a time deals the cards in order.
+        let rules = WinRules::default();
+        let names = ["ann", "bo", "cy"];
+        let clients: Vec<Client> =
+            names.iter().map(|name| Client::join(addr, name, &rules).unwrap()).collect();
+        assert_eq!(clients[0].card().position(22), Some((0, 0)));
+        let playing: Vec<_> =
+            clients.into_iter().map(|c| thread::spawn(move || c.play())).collect();
+
+        let winners = running.join().unwrap().unwrap();
+        let results: Vec<(&str, usize, usize, usize)> = winners.iter()
+            .map(|w| (w.player.as_str(), w.card, w.round, w.score))
+            .collect();
+        assert_eq!(results, vec![("cy", 2, 12, 4512),
+                                 ("ann", 0, expected[0].0, expected[0].1),
+                                 ("bo", 1, 15, 1924)]);
+
+        // Everyone hears every call up to the last win, and every win.
+        for messages in playing.into_iter().map(|p| p.join().unwrap().unwrap()) {
+            let calls = messages.iter().filter(|m| matches!(m, Message::Call { .. })).count();
+            let wins = messages.iter().filter(|m| matches!(m, Message::Win { .. })).count();
+            assert_eq!((calls, wins, messages.last()), (15, 3, Some(&Message::End)));
+        }
+    }
+
+    #[test]
+    fn test_bad_requests() {
+        // Calls far enough apart that only the first one happens.
+        let d = Day4::load_str("1,2,3\n\n1 2\n3 4\n");
+        let server = Server::bind("127.0.0.1:0", d, config(1, 60_000)).unwrap();
+        let addr = server.local_addr().unwrap();
+        let running = thread::spawn(move || server.run());
+
+        // An endless line gets an error and the connection dropped, not buffered.
+        let mut flood = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(flood.try_clone().unwrap());
+        write!(flood, "JOIN {}", "x".repeat(100 * MAX_REQUEST)).unwrap();
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        assert_eq!(reply, "ERROR line longer than 1024 bytes\n");
+        assert_eq!(reader.read_line(&mut reply).unwrap(), 0);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut calls = 0;
+        // Sends a line and returns the reply, counting calls on the way.
+        let mut ask = |line: &str| {
+            writeln!(stream, "{}", line).unwrap();
+            loop {
+                let mut reply = String::new();
+                reader.read_line(&mut reply).unwrap();
+                if reply.starts_with("CALL") {
+                    calls += 1;
+                } else {
+                    return reply.trim_end().to_string();
+                }
+            }
+        };
+        assert_eq!(ask("HELLO"), "ERROR unknown request 'HELLO'");
+        assert_eq!(ask("CLAIM"), "ERROR join first");
+        assert_eq!(ask("JOIN dee"), "CARD 2x2 1 2 3 4");
+        assert_eq!(ask("JOIN dee"), "ERROR already joined");
+        assert_eq!(ask("CLAIM"), "REJECT no winning line");
+        assert_eq!(calls, 1);
+
+        // A second player is turned away once the game is on.
+        let late = TcpStream::connect(addr).unwrap();
+        let mut turned_away = String::new();
+        BufReader::new(late).read_line(&mut turned_away).unwrap();
+        assert_eq!(turned_away, "ERROR game already started\n");
+
+        writeln!(stream, "QUIT").unwrap();
+        assert_eq!(running.join().unwrap().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_waiting_connections() {
+        let d = Day4::load_str("1,2,3\n\n1 2\n3 4\n");
+        let config = ServerConfig { join_timeout: Duration::from_millis(500), ..config(1, 50) };
+        let server = Server::bind("127.0.0.1:0", d, config).unwrap();
+        let addr = server.local_addr().unwrap();
+        let running = thread::spawn(move || server.run());
+
+        // The one line the server sends a connection that hasn't joined before hanging up.
+        let last_words = |stream: TcpStream| {
+            let mut reader = BufReader::new(stream);
+            let (mut line, mut rest) = (String::new(), String::new());
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(reader.read_line(&mut rest).unwrap(), 0);
+            line
+        };
+
+        // Only so many can wait, and not for long.
+        let idle: Vec<TcpStream> =
+            (0..MAX_WAITING).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        let extra = TcpStream::connect(addr).unwrap();
+        assert_eq!(last_words(extra), "ERROR too many connections waiting to join\n");
+        for stream in idle {
+            assert_eq!(last_words(stream), "ERROR took too long to join\n");
+        }
+
+        // Any still waiting when the game starts are let go then.
+        let early = TcpStream::connect(addr).unwrap();
+        let messages = Client::join(addr, "ann", &WinRules::default()).unwrap().play().unwrap();
+        assert_eq!(last_words(early), "ERROR game already started\n");
+        assert_eq!(messages.last(), Some(&Message::End));
+        assert_eq!(running.join().unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_stalled_player() {
+        // Enough calls, with none of them winning, to fill the buffers of a player who never
+        // reads.  The server drops them and carries on with the other one.
+        let calls = vec!["5"; 600_000].join(",");
+        let d = Day4::load_str(&format!("{}\n\n1 2\n3 4\n\n6 7\n8 9\n", calls));
+        let server = Server::bind("127.0.0.1:0", d, config(2, 0)).unwrap();
+        let addr = server.local_addr().unwrap();
+        let running = thread::spawn(move || server.run());
+
+        let rules = WinRules::default();
+        let _stalled = Client::join(addr, "stuck", &rules).unwrap();
+        let mut heard = 0;
+        Client::join(addr, "ann", &rules).unwrap()
+            .play_live(|m| heard += matches!(m, Message::Call { .. }) as usize)
+            .unwrap();
+        assert_eq!(heard, 600_000);
+        assert_eq!(running.join().unwrap().unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,159 @@
+// C ABI for embedding the solvers, built with `--features capi`.  The matching header
+// lives in include/rs_aoc2021.h and is regenerated by build.rs whenever this file changes.
+//
+// Ownership: an AocDay returned by aoc_day_new() must be released with aoc_day_free(),
+// and any error string handed back through an `error` out-parameter must be released
+// with aoc_string_free().  Nothing else is allocated on the caller's behalf.
+
+use crate::day::Day;
+use std::any::Any;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str;
+
+/// Call succeeded.
+pub const AOC_OK: c_int = 0;
+/// Call failed, see the error string.
+pub const AOC_ERROR: c_int = -1;
+
+/// A loaded puzzle day.  Opaque to C.
+pub struct AocDay {
+    day: Box<dyn Day>,
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("panic")
+    }
+}
+
+// Hands msg to the caller through the error out-parameter, if they gave us one.
+unsafe fn set_error(error: *mut *mut c_char, msg: &str) {
+    if !error.is_null() {
+        // Interior NULs can't cross the C boundary, drop them.
+        let msg = CString::new(msg.replace('\0', "")).unwrap();
+        *error = msg.into_raw();
+    }
+}
+
+/// Loads puzzle `day` (1-based) from an input buffer of `len` bytes.
+///
+/// Returns NULL on failure and, if `error` is not NULL, stores a message in `*error`
+/// which must be released with aoc_string_free().
+///
+/// # Safety
+///
+/// `input` must point to `len` readable bytes, and `error` must be NULL or point to
+/// writable storage for one pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aoc_day_new(
+    day: u32,
+    input: *const u8,
+    len: usize,
+    error: *mut *mut c_char,
+) -> *mut AocDay {
+    if input.is_null() && len > 0 {
+        set_error(error, "input is NULL");
+        return ptr::null_mut();
+    }
+    let bytes = if len == 0 { &[][..] } else { slice::from_raw_parts(input, len) };
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            set_error(error, &format!("input is not valid UTF-8: {}", e));
+            return ptr::null_mut();
+        }
+    };
+
+    match panic::catch_unwind(|| crate::load_day(day as usize, text)) {
+        Ok(Some(day)) => Box::into_raw(Box::new(AocDay { day })),
+        Ok(None) => {
+            set_error(error, &format!("no such day: {}", day));
+            ptr::null_mut()
+        }
+        Err(payload) => {
+            set_error(error, &format!("failed to load day {}: {}", day, panic_message(payload)));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases a day returned by aoc_day_new().  Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `day` must be NULL or a pointer from aoc_day_new() that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aoc_day_free(day: *mut AocDay) {
+    if !day.is_null() {
+        drop(Box::from_raw(day));
+    }
+}
+
+/// Solves `part` (1 or 2) of a loaded day.
+///
+/// Returns AOC_OK and stores the answer in `*answer`, or returns AOC_ERROR and, if
+/// `error` is not NULL, stores a message in `*error` which must be released with
+/// aoc_string_free().
+///
+/// # Safety
+///
+/// `day` must be a live pointer from aoc_day_new(), `answer` must point to writable
+/// storage for a uint64_t, and `error` must be NULL or point to writable storage for
+/// one pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aoc_day_solve(
+    day: *const AocDay,
+    part: u32,
+    answer: *mut u64,
+    error: *mut *mut c_char,
+) -> c_int {
+    if day.is_null() || answer.is_null() {
+        set_error(error, "day and answer must not be NULL");
+        return AOC_ERROR;
+    }
+    let day = &(*day).day;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let result = match part {
+            1 => day.part1(),
+            2 => day.part2(),
+            _ => return Err(format!("no such part: {}", part)),
+        };
+        result.map_err(|msg| msg.to_string())
+    }));
+
+    match result {
+        Ok(Ok(value)) => {
+            *answer = value as u64;
+            AOC_OK
+        }
+        Ok(Err(msg)) => {
+            set_error(error, &msg);
+            AOC_ERROR
+        }
+        Err(payload) => {
+            set_error(error, &format!("part {} failed: {}", part, panic_message(payload)));
+            AOC_ERROR
+        }
+    }
+}
+
+/// Releases an error string handed out by this library.  Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string from this library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aoc_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -1,4 +1,29 @@
+// A puzzle constant that can be overridden at run time, e.g. the number of generations.  Days
+// with any list them in a PARAMS table, so they can be listed without loading any input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub name: &'static str,
+    pub help: &'static str,
+    // Puzzle values for part 1 and part 2, None if that part doesn't use it.
+    pub defaults: (Option<usize>, Option<usize>),
+}
+
 pub trait Day {
     fn part1(&self) -> Result<usize, &str>;
     fn part2(&self) -> Result<usize, &str>;
+
+    // Intermediate results behind the answers, as (name, value) pairs in display order.
+    fn explain(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    // Parameters this day understands.
+    fn params(&self) -> Vec<Param> {
+        Vec::new()
+    }
+
+    // Overrides a parameter for every part that uses it.
+    fn set_param(&mut self, name: &str, _value: usize) -> Result<(), String> {
+        Err(format!("unknown parameter: {}", name))
+    }
 }
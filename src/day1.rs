@@ -1,24 +1,50 @@
-use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use crate::day::{Day, Param};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+
+// Sliding window size for part 2.
+const WINDOW: usize = 3;
+
+pub const PARAMS: &[Param] = &[Param {
+    name: "window",
+    help: "sliding window size",
+    defaults: (None, Some(WINDOW)),
+}];
 
 pub struct Day1 {
     depths: Vec<usize>,
+    // Overrides WINDOW
+    window: Option<usize>,
+    // Noise filters applied to the depths before counting increases.
+    filters: FilterPipeline,
 }
 
 impl Day1 {
     pub fn load(filename: &str) -> Day1 {
-        let mut depths: Vec<usize> = Vec::new();
+        Day1::load_str(&fs::read_to_string(filename).unwrap())
+    }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+    pub fn load_str(input: &str) -> Day1 {
+        let mut depths: Vec<usize> = Vec::new();
 
-        for line in reader.lines() {
-            let l = &line.unwrap();
+        for l in input.lines() {
             depths.push(l.parse::<usize>().unwrap());
         }
 
-        Day1 { depths }
+        Day1 { depths, window: None, filters: FilterPipeline::default() }
+    }
+
+    pub fn set_filters(&mut self, filters: FilterPipeline) {
+        self.filters = filters;
+    }
+
+    // Depths after the filter pipeline.
+    fn filtered(&self) -> Vec<f64> {
+        let depths: Vec<f64> = self.depths.iter().map(|d| *d as f64).collect();
+        self.filters.apply(&depths)
     }
 
     fn increases(&self) -> usize {
@@ -32,32 +58,443 @@ impl Day1 {
     }
 
     fn avg_increases(&self, window: usize) -> usize {
-        let mut avg_depths: Vec<f32> = Vec::new();
-        for n in 0..self.depths.len() - window + 1 {
-            let mut sum = 0;
-            for i in 0..window {
-                sum += self.depths[n + i];
+        let mut sweep = SweepAnalyzer::new(&[window]);
+        for depth in &self.depths {
+            sweep.push(*depth);
+        }
+
+        sweep.increases(window).unwrap()
+    }
+
+    // Longest run of consecutive readings that keep getting deeper (or shallower).
+    fn longest_run(&self, deeper: bool) -> Run {
+        let mut best = Run { start: 0, len: self.depths.len().min(1) };
+        let mut start = 0;
+        for n in 1..self.depths.len() {
+            let continues = if deeper {
+                self.depths[n] > self.depths[n - 1]
+            } else {
+                self.depths[n] < self.depths[n - 1]
+            };
+            if !continues {
+                start = n;
+            }
+            if n + 1 - start > best.len {
+                best = Run { start, len: n + 1 - start };
             }
-            avg_depths.push((sum as f32) / (window as f32));
         }
 
-        let mut count = 0;
-        for n in 1..avg_depths.len() {
-            if avg_depths[n - 1] < avg_depths[n] {
-                count += 1;
+        best
+    }
+
+    // Biggest decrease in depth between two consecutive readings.
+    fn largest_drop(&self) -> Option<DepthDrop> {
+        let mut largest: Option<DepthDrop> = None;
+        for n in 1..self.depths.len() {
+            let (from, to) = (self.depths[n - 1], self.depths[n]);
+            if to < from && largest.as_ref().map_or(0, |d| d.from - d.to) < from - to {
+                largest = Some(DepthDrop { index: n, from, to });
             }
         }
-        count
+
+        largest
+    }
+
+    // Counts of depths in `bins` equal width buckets spanning the shallowest to deepest reading.
+    fn histogram(&self, bins: usize) -> Vec<Bucket> {
+        let (min, max) = match (self.depths.iter().min(), self.depths.iter().max()) {
+            (Some(min), Some(max)) => (*min, *max),
+            _ => return Vec::new(),
+        };
+        let bins = bins.max(1);
+        let width = ((max - min) / bins + 1).max(1);
+
+        let mut buckets: Vec<Bucket> = Vec::new();
+        let mut low = min;
+        while low <= max {
+            let high = low.saturating_add(width - 1).min(max);
+            buckets.push(Bucket { low, high, count: 0 });
+            if high == max {
+                break;
+            }
+            low = high + 1;
+        }
+        for depth in &self.depths {
+            buckets[(depth - min) / width].count += 1;
+        }
+
+        buckets
+    }
+
+    // Summary of the depth profile, with increase counts for each window size in `windows`.
+    pub fn report(&self, windows: &[usize], bins: usize) -> DepthReport {
+        let mut sweep = SweepAnalyzer::new(windows);
+        for depth in &self.depths {
+            sweep.push(*depth);
+        }
+
+        DepthReport {
+            readings: self.depths.len(),
+            longest_deeper_run: self.longest_run(true),
+            longest_shallower_run: self.longest_run(false),
+            largest_drop: self.largest_drop(),
+            window_increases: sweep.all_increases().iter()
+                .map(|(window, increases)| WindowIncreases { window: *window, increases: *increases })
+                .collect(),
+            histogram: self.histogram(bins),
+        }
+    }
+}
+
+// A run of `len` consecutive readings starting at index `start`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Run {
+    pub start: usize,
+    pub len: usize,
+}
+
+// Depth went from `from` at reading index-1 to `to` at reading index.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DepthDrop {
+    pub index: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WindowIncreases {
+    pub window: usize,
+    pub increases: usize,
+}
+
+// Number of depths from low to high, inclusive.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Bucket {
+    pub low: usize,
+    pub high: usize,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DepthReport {
+    pub readings: usize,
+    pub longest_deeper_run: Run,
+    pub longest_shallower_run: Run,
+    pub largest_drop: Option<DepthDrop>,
+    pub window_increases: Vec<WindowIncreases>,
+    pub histogram: Vec<Bucket>,
+}
+
+impl DepthReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for DepthReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "readings: {}", self.readings)?;
+        writeln!(f, "longest deeper run: {} readings from index {}",
+                 self.longest_deeper_run.len, self.longest_deeper_run.start)?;
+        writeln!(f, "longest shallower run: {} readings from index {}",
+                 self.longest_shallower_run.len, self.longest_shallower_run.start)?;
+        match &self.largest_drop {
+            Some(d) => writeln!(f, "largest drop: {} ({} -> {} at index {})", d.from - d.to, d.from, d.to, d.index)?,
+            None => writeln!(f, "largest drop: none")?,
+        }
+        writeln!(f, "increases by window:")?;
+        for w in &self.window_increases {
+            writeln!(f, "  {:>4}: {}", w.window, w.increases)?;
+        }
+        writeln!(f, "histogram:")?;
+        let widest = self.histogram.iter().map(|b| b.count).max().unwrap_or(0).max(1);
+        for b in &self.histogram {
+            // Bars are scaled to at most 40 characters.
+            let bar = "#".repeat((b.count * 40).div_ceil(widest));
+            writeln!(f, "  {:>8}-{:<8} {:>6} {}", b.low, b.high, b.count, bar)?;
+        }
+
+        Ok(())
     }
 }
 
+// Counts sliding window increases for several window sizes in one pass over a stream of
+// depths.  Only the last max(window) depths are kept, so memory doesn't grow with the input.
+pub struct SweepAnalyzer {
+    windows: Vec<usize>,
+    // Most recent depths, oldest first, at most max(windows) of them.
+    recent: VecDeque<usize>,
+    // Exact sum of the current window, per window size.
+    sums: Vec<u128>,
+    // Sum of the previous full window, per window size.
+    prev_sums: Vec<Option<u128>>,
+    counts: Vec<usize>,
+    depths_seen: usize,
+}
+
+impl SweepAnalyzer {
+    // Window sizes of 0 are ignored.
+    pub fn new(windows: &[usize]) -> SweepAnalyzer {
+        let mut windows: Vec<usize> = windows.iter().copied().filter(|w| *w > 0).collect();
+        windows.sort_unstable();
+        windows.dedup();
+        let n = windows.len();
+
+        SweepAnalyzer {
+            windows,
+            recent: VecDeque::new(),
+            sums: vec![0; n],
+            prev_sums: vec![None; n],
+            counts: vec![0; n],
+            depths_seen: 0,
+        }
+    }
+
+    // Analyzes a stream with one depth per line, blank lines are skipped.
+    pub fn analyze<R: BufRead>(reader: R, windows: &[usize]) -> io::Result<SweepAnalyzer> {
+        let mut sweep = SweepAnalyzer::new(windows);
+
+        for (line_no, line) in reader.lines().enumerate() {
+            let line = line?;
+            let l = line.trim();
+            if l.is_empty() {
+                continue;
+            }
+            match l.parse::<usize>() {
+                Ok(depth) => sweep.push(depth),
+                Err(e) => {
+                    let msg = format!("line {}: bad depth {:?}: {}", line_no + 1, l, e);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+
+        Ok(sweep)
+    }
+
+    pub fn push(&mut self, depth: usize) {
+        for (i, window) in self.windows.iter().enumerate() {
+            // Slide the window: add the new depth, drop the one falling off the back.
+            self.sums[i] += depth as u128;
+            if self.recent.len() >= *window {
+                self.sums[i] -= self.recent[self.recent.len() - window] as u128;
+            }
+
+            if self.depths_seen + 1 >= *window {
+                if let Some(prev) = self.prev_sums[i] {
+                    if self.sums[i] > prev {
+                        self.counts[i] += 1;
+                    }
+                }
+                self.prev_sums[i] = Some(self.sums[i]);
+            }
+        }
+
+        self.recent.push_back(depth);
+        if self.recent.len() > *self.windows.last().unwrap_or(&0) {
+            self.recent.pop_front();
+        }
+        self.depths_seen += 1;
+    }
+
+    pub fn depths_seen(&self) -> usize {
+        self.depths_seen
+    }
+
+    // Number of times a window's sum was larger than the one before, None if the window
+    // size wasn't requested.
+    pub fn increases(&self, window: usize) -> Option<usize> {
+        self.windows.iter().position(|w| *w == window).map(|i| self.counts[i])
+    }
+
+    // (window size, increases) for every window, smallest window first.
+    pub fn all_increases(&self) -> Vec<(usize, usize)> {
+        self.windows.iter().copied().zip(self.counts.iter().copied()).collect()
+    }
+}
+
+// A noise filter over a series of depths.  Output may be shorter than the input, e.g. a
+// moving average only has values where the window is full.
+pub trait SonarFilter {
+    fn apply(&self, depths: &[f64]) -> Vec<f64>;
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+// Mean of each full window of `window` readings.
+pub struct MovingAverage {
+    pub window: usize,
+}
+
+impl SonarFilter for MovingAverage {
+    fn apply(&self, depths: &[f64]) -> Vec<f64> {
+        depths.windows(self.window).map(|w| w.iter().sum::<f64>() / self.window as f64).collect()
+    }
+}
+
+// Median of each full window of `window` readings.
+pub struct MovingMedian {
+    pub window: usize,
+}
+
+impl SonarFilter for MovingMedian {
+    fn apply(&self, depths: &[f64]) -> Vec<f64> {
+        depths.windows(self.window).map(median).collect()
+    }
+}
+
+// Holds the last accepted depth until a reading moves more than `delta` away from it, so
+// only rises bigger than delta count as increases.
+pub struct Hysteresis {
+    pub delta: f64,
+}
+
+impl SonarFilter for Hysteresis {
+    fn apply(&self, depths: &[f64]) -> Vec<f64> {
+        let mut level = match depths.first() {
+            Some(first) => *first,
+            None => return Vec::new(),
+        };
+
+        depths.iter().map(|depth| {
+            if (depth - level).abs() > self.delta {
+                level = *depth;
+            }
+            level
+        }).collect()
+    }
+}
+
+// Replaces a reading that's more than `limit` away from the median of the previous `window`
+// accepted readings with that median.
+pub struct OutlierRejection {
+    pub window: usize,
+    pub limit: f64,
+}
+
+impl SonarFilter for OutlierRejection {
+    fn apply(&self, depths: &[f64]) -> Vec<f64> {
+        let mut accepted: Vec<f64> = Vec::with_capacity(depths.len());
+        for depth in depths {
+            let start = accepted.len().saturating_sub(self.window);
+            let value = if accepted.is_empty() {
+                *depth
+            } else {
+                let expected = median(&accepted[start..]);
+                if (depth - expected).abs() > self.limit { expected } else { *depth }
+            };
+            accepted.push(value);
+        }
+
+        accepted
+    }
+}
+
+// Filters applied in order.  Empty by default, which leaves depths untouched.
+#[derive(Default)]
+pub struct FilterPipeline {
+    filters: Vec<Box<dyn SonarFilter>>,
+}
+
+impl FilterPipeline {
+    pub fn push(&mut self, filter: Box<dyn SonarFilter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    // Parses a comma separated list of filters, applied left to right:
+    //   mean:W           moving average over W readings
+    //   median:W         moving median over W readings
+    //   hysteresis:D     ignore moves of D or less
+    //   outlier:W:L      reject readings more than L from the median of the last W
+    pub fn parse(spec: &str) -> Result<FilterPipeline, String> {
+        let mut pipeline = FilterPipeline::default();
+
+        for item in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let parts: Vec<&str> = item.split(':').collect();
+            let arg = |n: usize| -> Result<f64, String> {
+                parts.get(n)
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .filter(|v| *v >= 0.0)
+                    .ok_or_else(|| format!("bad filter {:?}", item))
+            };
+            let window = |n: usize| -> Result<usize, String> {
+                parts.get(n)
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .filter(|w| *w > 0)
+                    .ok_or_else(|| format!("bad window in filter {:?}", item))
+            };
+
+            let filter: Box<dyn SonarFilter> = match (parts[0], parts.len()) {
+                ("mean", 2) => Box::new(MovingAverage { window: window(1)? }),
+                ("median", 2) => Box::new(MovingMedian { window: window(1)? }),
+                ("hysteresis", 2) => Box::new(Hysteresis { delta: arg(1)? }),
+                ("outlier", 3) => Box::new(OutlierRejection { window: window(1)?, limit: arg(2)? }),
+                _ => return Err(format!("unknown filter {:?}", item)),
+            };
+            pipeline.push(filter);
+        }
+
+        Ok(pipeline)
+    }
+
+    pub fn apply(&self, depths: &[f64]) -> Vec<f64> {
+        let mut series = depths.to_vec();
+        for filter in &self.filters {
+            series = filter.apply(&series);
+        }
+
+        series
+    }
+}
+
+fn count_increases(series: &[f64]) -> usize {
+    series.windows(2).filter(|pair| pair[1] > pair[0]).count()
+}
+
 impl Day for Day1 {
     fn part1(&self) -> Result<usize, &str> {
-        Ok(self.increases())
+        if self.filters.is_empty() {
+            return Ok(self.increases());
+        }
+
+        Ok(count_increases(&self.filtered()))
     }
 
     fn part2(&self) -> Result<usize, &str> {
-        Ok(self.avg_increases(3))
+        let window = self.window.unwrap_or(WINDOW);
+        if self.filters.is_empty() {
+            return Ok(self.avg_increases(window));
+        }
+
+        Ok(count_increases(&MovingAverage { window }.apply(&self.filtered())))
+    }
+
+    fn params(&self) -> Vec<Param> {
+        PARAMS.to_vec()
+    }
+
+    fn set_param(&mut self, name: &str, value: usize) -> Result<(), String> {
+        match name {
+            "window" if value == 0 => Err(String::from("window must be at least 1")),
+            "window" => {
+                self.window = Some(value);
+                Ok(())
+            }
+            _ => Err(format!("unknown parameter: {}", name)),
+        }
     }
 }
 
@@ -83,4 +520,135 @@ mod tests {
         let d = Day1::load("data/day1_example1.txt");
         assert_eq!(d.avg_increases(3), 5);
     }
+
+    #[test]
+    fn test_avg_increases_short() {
+        // Fewer depths than the window: no full window, so no increases.
+        let d = Day1::load_str("199\n200\n");
+        assert_eq!(d.avg_increases(3), 0);
+        let d = Day1::load_str("");
+        assert_eq!(d.avg_increases(3), 0);
+    }
+
+    #[test]
+    fn test_sweep_analyzer() {
+        let input = "199\n200\n208\n210\n200\n207\n240\n269\n260\n263\n";
+        let sweep = SweepAnalyzer::analyze(input.as_bytes(), &[3, 1, 0, 3, 20]).unwrap();
+        assert_eq!(sweep.depths_seen(), 10);
+        assert_eq!(sweep.all_increases(), vec![(1, 7), (3, 5), (20, 0)]);
+        assert_eq!(sweep.increases(3), Some(5));
+        assert_eq!(sweep.increases(2), None);
+        assert_eq!(sweep.recent.len(), 10);
+
+        // Only max(window) depths are kept.
+        let sweep = SweepAnalyzer::analyze(input.as_bytes(), &[1, 3]).unwrap();
+        assert_eq!(sweep.recent.len(), 3);
+    }
+
+    #[test]
+    fn test_sweep_analyzer_exact() {
+        // Windows whose sums differ by 1 in the low bits, where an f32 average can't tell
+        // them apart.
+        let big = 1usize << 40;
+        let mut sweep = SweepAnalyzer::new(&[3]);
+        for depth in [big, big, big, big + 1] {
+            sweep.push(depth);
+        }
+        assert_eq!(sweep.increases(3), Some(1));
+    }
+
+    #[test]
+    fn test_sweep_analyzer_bad_input() {
+        let err = SweepAnalyzer::analyze("1\n2\nthree\n".as_bytes(), &[1]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 3"));
+    }
+
+    #[test]
+    fn test_report() {
+        let d = Day1::load_str("199\n200\n208\n210\n200\n207\n240\n269\n260\n263\n");
+        let report = d.report(&[1, 2, 3], 4);
+        assert_eq!(report.readings, 10);
+        assert_eq!(report.longest_deeper_run, Run { start: 0, len: 4 });
+        assert_eq!(report.longest_shallower_run, Run { start: 3, len: 2 });
+        assert_eq!(report.largest_drop, Some(DepthDrop { index: 4, from: 210, to: 200 }));
+        assert_eq!(report.window_increases[2], WindowIncreases { window: 3, increases: 5 });
+        assert_eq!(report.histogram, vec![
+            Bucket { low: 199, high: 216, count: 6 },
+            Bucket { low: 217, high: 234, count: 0 },
+            Bucket { low: 235, high: 252, count: 1 },
+            Bucket { low: 253, high: 269, count: 3 },
+        ]);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["largest_drop"]["from"], 210);
+        assert!(report.to_string().contains("largest drop: 10 (210 -> 200 at index 4)"));
+    }
+
+    #[test]
+    fn test_report_empty() {
+        let report = Day1::load_str("").report(&[1], 10);
+        assert_eq!(report.longest_deeper_run, Run { start: 0, len: 0 });
+        assert_eq!(report.largest_drop, None);
+        assert!(report.histogram.is_empty());
+    }
+
+    #[test]
+    fn test_moving_filters() {
+        let series = [1.0, 5.0, 2.0, 8.0, 3.0];
+        assert_eq!(MovingAverage { window: 2 }.apply(&series), vec![3.0, 3.5, 5.0, 5.5]);
+        assert_eq!(MovingMedian { window: 3 }.apply(&series), vec![2.0, 5.0, 3.0]);
+        assert!(MovingMedian { window: 6 }.apply(&series).is_empty());
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        // Three levels, each 10 above the last, with +-1 jitter: every wiggle counts without
+        // hysteresis.
+        let series = [100.0, 101.0, 100.0, 101.0, 100.0,
+                      110.0, 111.0, 110.0, 111.0, 110.0,
+                      120.0, 121.0, 120.0, 121.0, 120.0];
+        assert_eq!(count_increases(&series), 8);
+        let filtered = Hysteresis { delta: 2.0 }.apply(&series);
+        assert_eq!(count_increases(&filtered), 2);
+
+        // Pure jitter around a constant depth is not an increase at all.
+        let jitter = [100.0, 101.0, 100.0, 101.0, 100.0];
+        assert_eq!(count_increases(&Hysteresis { delta: 1.0 }.apply(&jitter)), 0);
+    }
+
+    #[test]
+    fn test_outlier_rejection() {
+        let series = [100.0, 101.0, 900.0, 102.0, 5.0, 103.0];
+        let filtered = OutlierRejection { window: 3, limit: 50.0 }.apply(&series);
+        assert_eq!(filtered, vec![100.0, 101.0, 100.5, 102.0, 101.0, 103.0]);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        assert!(FilterPipeline::parse("").unwrap().is_empty());
+        assert!(FilterPipeline::parse("median:0").is_err());
+        assert!(FilterPipeline::parse("smooth:3").is_err());
+        assert!(FilterPipeline::parse("outlier:3").is_err());
+
+        let mut d = Day1::load_str("199\n200\n208\n210\n200\n207\n240\n269\n260\n263\n");
+        d.set_filters(FilterPipeline::parse("mean:1").unwrap());
+        assert_eq!(d.part1(), Ok(7));
+        assert_eq!(d.part2(), Ok(5));
+
+        // The spike is removed first, then moves of 2 or less are ignored.
+        let mut d = Day1::load_str("100\n102\n101\n103\n500\n104\n103\n106\n108\n107\n110\n");
+        assert_eq!(d.part1(), Ok(6));
+        d.set_filters(FilterPipeline::parse("outlier:3:50, hysteresis:2").unwrap());
+        assert_eq!(d.part1(), Ok(3));
+    }
+
+    #[test]
+    fn test_set_param() {
+        let mut d = Day1::load_str("199\n200\n208\n210\n200\n207\n240\n269\n260\n263\n");
+        assert_eq!(d.part2(), Ok(5));
+        d.set_param("window", 1).unwrap();
+        assert_eq!(d.part2(), Ok(7));
+        assert!(d.set_param("window", 0).is_err());
+    }
 }
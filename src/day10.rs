@@ -1,8 +1,7 @@
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs;
 // use std::intrinsics::assume;
 use crate::day::Day;
-use std::io::{BufRead, BufReader};
 
 pub struct Day10 {
     lines: Vec<String>,
@@ -10,15 +9,15 @@ pub struct Day10 {
 
 impl Day10 {
     pub fn load(filename: &str) -> Day10 {
+        Day10::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day10 {
         // println!("Loading.");
         let mut lines: Vec<String> = Vec::new();
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = line.unwrap();
-            lines.push(l);
+        for l in input.lines() {
+            lines.push(l.to_string());
         }
 
         Day10 { lines }
@@ -1,22 +1,32 @@
-use crate::day::Day;
+use crate::day::{Day, Param};
 use array2d::Array2D;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
+
+// Steps simulated for part 1.
+const STEPS: usize = 100;
+
+pub const PARAMS: &[Param] = &[Param {
+    name: "steps",
+    help: "steps to count flashes over",
+    defaults: (Some(STEPS), None),
+}];
 
 pub struct Day11 {
     energy: Array2D<usize>,
+    // Overrides STEPS
+    steps: Option<usize>,
 }
 
 impl Day11 {
     pub fn load(filename: &str) -> Day11 {
-        let mut cavern = Array2D::filled_with(0, 10, 10);
+        Day11::load_str(&fs::read_to_string(filename).unwrap())
+    }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+    pub fn load_str(input: &str) -> Day11 {
+        let mut cavern = Array2D::filled_with(0, 10, 10);
 
         let mut row: usize = 0;
-        for line in reader.lines() {
-            let l = line.unwrap();
+        for l in input.lines() {
             let mut col: usize = 0;
             for c in l.chars() {
                 let energy = c.to_digit(10).unwrap() as usize;
@@ -26,7 +36,7 @@ impl Day11 {
             row += 1;
         }
 
-        Day11 { energy: cavern }
+        Day11 { energy: cavern, steps: None }
     }
 
     // Updates energy matrix for one step, returns number of flashes in this step.
@@ -113,12 +123,26 @@ impl Day11 {
 
 impl Day for Day11 {
     fn part1(&self) -> Result<usize, &str> {
-        Ok(self.do_steps(100))
+        Ok(self.do_steps(self.steps.unwrap_or(STEPS)))
     }
 
     fn part2(&self) -> Result<usize, &str> {
         Ok(self.to_sync())
     }
+
+    fn params(&self) -> Vec<Param> {
+        PARAMS.to_vec()
+    }
+
+    fn set_param(&mut self, name: &str, value: usize) -> Result<(), String> {
+        match name {
+            "steps" => {
+                self.steps = Some(value);
+                Ok(())
+            }
+            _ => Err(format!("unknown parameter: {}", name)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,5 @@
 use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use lazy_static::lazy_static;
@@ -49,6 +48,10 @@ impl Day12 {
     }
 
     pub fn load(filename: &str) -> Day12 {
+        Day12::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day12 {
         let mut day12 = Day12::new();
 
         lazy_static! {
@@ -56,11 +59,7 @@ impl Day12 {
                 Regex::new("([a-zA-Z]+)-([a-zA-Z]+)").unwrap();
         }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = &line.unwrap();
+        for l in input.lines() {
             let caps = LINE_RE.captures(&l);
             match caps {
                 Some(caps) => {
@@ -1,6 +1,5 @@
 use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
@@ -18,6 +17,10 @@ pub struct Day13 {
 
 impl Day13 {
     pub fn load(filename: &str) -> Day13 {
+        Day13::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day13 {
         let mut coords: HashMap<(isize, isize), bool> = HashMap::new();
         let mut folds: Vec<(Axis, isize)> = Vec::new();
         lazy_static! {
@@ -25,12 +28,7 @@ impl Day13 {
             static ref FOLD_RE: Regex = Regex::new("fold along ([xy])=([0-9]+)").unwrap();
         }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = &line.unwrap();
-
+        for l in input.lines() {
             // Process coordinates
             let caps = COORD_RE.captures(&l);
             match caps {
@@ -1,18 +1,32 @@
-use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use crate::day::{Day, Param};
+use std::fs;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
 use std::mem;
 
+// Insertion steps for part 1 and part 2.
+const STEPS: (usize, usize) = (10, 40);
+
+pub const PARAMS: &[Param] = &[Param {
+    name: "steps",
+    help: "pair insertion steps to apply",
+    defaults: (Some(STEPS.0), Some(STEPS.1)),
+}];
+
 pub struct Day14 {
     initial: String,
     rules: HashMap<String, String>,
+    // Overrides STEPS for both parts
+    steps: Option<usize>,
 }
 
 impl Day14 {
     pub fn load(filename: &str) -> Day14 {
+        Day14::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day14 {
         let mut initial = String::from("");
         let mut rules: HashMap<String, String> = HashMap::new();
         lazy_static! {
@@ -22,11 +36,7 @@ impl Day14 {
                 Regex::new("([A-Z]+) -> ([A-Z])").unwrap();
         }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = &line.unwrap();
+        for l in input.lines() {
             let caps = INITIAL_RE.captures(&l);
             match caps {
                 Some(caps) => {
@@ -44,9 +54,12 @@ impl Day14 {
             }
         }
 
-        Day14 { initial, rules }
+        Day14 { initial, rules, steps: None }
     }
 
+    // The polymer itself, which doubles in length every step.  Only the tests use it now, to
+    // check score2 against.
+    #[cfg(test)]
     fn gen(&self, n: usize) -> String {
         let mut s1 = self.initial.to_string();
         let mut s2 = String::new();
@@ -80,6 +93,7 @@ impl Day14 {
         from.to_string()
     }
 
+    #[cfg(test)]
     fn score(&self, n: usize) -> usize {
         let s = self.gen(n);
 
@@ -105,7 +119,9 @@ impl Day14 {
         max - min
     }
 
-    fn score2(&self, n: usize) -> usize {
+    // Counts pairs rather than building the polymer, so it copes with any number of steps.
+    // None if the counts outgrow a usize.
+    fn score2(&self, n: usize) -> Option<usize> {
         // convert rules to a hashmap mapping a pair of letters to two new pairs.
         let mut conversions: HashMap<String, (String, String)> = HashMap::new();
         for s in self.rules.keys() {
@@ -125,13 +141,7 @@ impl Day14 {
         let pairs = &mut pairs1;
 
         for n in 0..self.initial.len()-1 {
-            let pair = &self.initial[n..n+2];
-            if pairs.contains_key(pair) {
-                *pairs.get_mut(pair).unwrap() += 1;
-            }
-            else {
-                pairs.insert(pair.to_string(), 1);
-            }
+            add_count(pairs, &self.initial[n..n+2], 1)?;
         }
 
         // pair counts at end of the generation process.
@@ -142,31 +152,21 @@ impl Day14 {
             for (pair, count) in pairs.iter() {
                 match conversions.get(pair) {
                     Some((s1, s2)) => {
-                        if pairs_out.contains_key(s1) {
-                            *pairs_out.get_mut(s1).unwrap() += *count;
-                        }
-                        else {
-                            pairs_out.insert(s1.to_string(), *count);
-                        }
-                        if pairs_out.contains_key(s2) {
-                            *pairs_out.get_mut(s2).unwrap() += *count;
-                        }
-                        else {
-                            pairs_out.insert(s2.to_string(), *count);
-                        }
+                        add_count(pairs_out, s1, *count)?;
+                        add_count(pairs_out, s2, *count)?;
                     }
                     None => {
                         // No change for this pair
-                        if pairs_out.contains_key(pair) {
-                            *pairs_out.get_mut(pair).unwrap() += *count;
-                        }
-                        else {
-                            pairs_out.insert(pair.to_string(), *count);
-                        }
+                        add_count(pairs_out, pair, *count)?;
                     }
                 }
             }
 
+            // Once no rule applies any more, later steps won't change anything either.
+            if pairs_out == pairs {
+                break;
+            }
+
             // use output of last generation for input of next
             mem::swap(pairs, pairs_out);
             pairs_out.clear();
@@ -175,20 +175,8 @@ impl Day14 {
         // Convert pair counts into character counts
         let mut char_count: HashMap<String, usize> = HashMap::new();
         for (pair, count) in pairs.iter() {
-            let c1 = &pair[0..1];
-            if char_count.contains_key(c1) {
-                *char_count.get_mut(c1).unwrap() += count;
-            }
-            else {
-                char_count.insert(c1.to_string(), *count);
-            }
-            let c2 = &pair[1..2];
-            if char_count.contains_key(c2) {
-                *char_count.get_mut(c2).unwrap() += count;
-            }
-            else {
-                char_count.insert(c2.to_string(), *count);
-            }
+            add_count(&mut char_count, &pair[0..1], *count)?;
+            add_count(&mut char_count, &pair[1..2], *count)?;
         }
 
         // Adjust counts for first and last chars.
@@ -198,8 +186,8 @@ impl Day14 {
         // and one to the last char's entry.
         let first = &self.initial[0..1];
         let last = &self.initial.chars().last().unwrap().to_string();
-        *char_count.get_mut(first).unwrap() += 1;
-        *char_count.get_mut(last).unwrap() += 1;
+        add_count(&mut char_count, first, 1)?;
+        add_count(&mut char_count, last, 1)?;
 
         // now divide by two
         for (_, count) in char_count.iter_mut() {
@@ -211,17 +199,38 @@ impl Day14 {
         let max = char_count.values().max().unwrap();
         let min = char_count.values().min().unwrap();
 
-        max - min
+        Some(max - min)
     }
 }
 
+// Adds n to a letter or pair count, None if it overflows.
+fn add_count(counts: &mut HashMap<String, usize>, key: &str, n: usize) -> Option<()> {
+    let count = counts.entry(key.to_string()).or_insert(0);
+    *count = count.checked_add(n)?;
+    Some(())
+}
+
 impl Day for Day14 {
     fn part1(&self) -> Result<usize, &str> {
-        Ok(self.score(10))
+        self.score2(self.steps.unwrap_or(STEPS.0)).ok_or("element counts overflow")
     }
 
     fn part2(&self) -> Result<usize, &str> {
-        Ok(self.score2(40))
+        self.score2(self.steps.unwrap_or(STEPS.1)).ok_or("element counts overflow")
+    }
+
+    fn params(&self) -> Vec<Param> {
+        PARAMS.to_vec()
+    }
+
+    fn set_param(&mut self, name: &str, value: usize) -> Result<(), String> {
+        match name {
+            "steps" => {
+                self.steps = Some(value);
+                Ok(())
+            }
+            _ => Err(format!("unknown parameter: {}", name)),
+        }
     }
 }
 
@@ -257,15 +266,25 @@ mod tests {
     fn test_score2() {
         let d = Day14::load("data/day14_example1.txt");
         let score = d.score2(10);
-        assert_eq!(score, 1588);
+        assert_eq!(score, Some(1588));
         let score = d.score2(40);
-        assert_eq!(score, 2188189693529);
+        assert_eq!(score, Some(2188189693529));
+        for n in 0..8 {
+            assert_eq!(d.score2(n), Some(d.score(n)));
+        }
+        // The polymer doubles every step, so counts overflow long before this.
+        assert_eq!(d.score2(1 << 40), None);
+        // With no rule that applies it stays as it is, however many steps.
+        assert_eq!(Day14::load_str("NNCB\n\nAB -> C\n").score2(1 << 40), Some(1));
     }
 
     #[test]
     fn test_part1() {
         let d = Day14::load("data/day14_example1.txt");
         assert_eq!(d.part1(), Ok(1588));
+        let mut d = d;
+        d.set_param("steps", 1 << 40).unwrap();
+        assert_eq!(d.part1(), Err("element counts overflow"));
     }
 
 
@@ -1,33 +1,55 @@
 use std::collections::HashMap;
-use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use crate::day::{Day, Param};
+use std::fs;
 use priority_queue::PriorityQueue;
 
+// How many times the map is tiled in each direction for part 2.
+const TILES: usize = 5;
+
+pub const PARAMS: &[Param] = &[Param {
+    name: "tiles",
+    help: "times the map repeats in each direction",
+    defaults: (None, Some(TILES)),
+}];
+
 pub struct Day15 {
     // Risk array HashMap maps coordinate: (usize, usize) to risk: usize
     risk: HashMap<(usize, usize), usize>,
     max_x: usize,
     max_y: usize,
+    // Overrides TILES
+    tiles: Option<usize>,
 }
 
 impl Day15 {
     pub fn load(filename: &str) -> Day15 {
-        let mut risk: HashMap<(usize, usize), usize> = HashMap::new();
+        Day15::load_str(&fs::read_to_string(filename).unwrap())
+    }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+    pub fn load_str(input: &str) -> Day15 {
+        Day15::parse(input).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // The map is a rectangle of risk digits.
+    pub fn parse(input: &str) -> Result<Day15, String> {
+        let mut risk: HashMap<(usize, usize), usize> = HashMap::new();
 
         let mut max_x = 0;
         let mut max_y= 0;
 
+        let width = input.lines().next().map_or(0, |l| l.chars().count());
         let mut y = 0;
-        for line in reader.lines() {
-            let l = &line.unwrap();
+        for l in input.lines() {
             let mut x = 0;
+            if l.chars().count() != width {
+                return Err(format!("line {} is not as wide as the first", y + 1));
+            }
             for c in l.chars() {
                 // Get one digit and store it.
-                let val = c.to_digit(10).unwrap() as usize;
+                let val = match c.to_digit(10) {
+                    Some(d) => d as usize,
+                    None => return Err(format!("line {}: '{}' is not a risk level", y + 1, c)),
+                };
                 risk.insert((x, y), val);
 
                 // Record max X and Y stored.
@@ -43,12 +65,15 @@ impl Day15 {
             }
             y += 1;
         }
+        if risk.is_empty() {
+            return Err(String::from("empty map"));
+        }
 
-        Day15 { risk, max_x, max_y }
+        Ok(Day15 { risk, max_x, max_y, tiles: None })
     }
 }
 
-struct Solver {
+pub(crate) struct Solver {
     // (x, y, total_risk), priority is usize::MAX-total_risk
     frontier: PriorityQueue<(usize, usize, usize), usize>,
     total_risk: Vec<Vec<usize>>,
@@ -60,7 +85,7 @@ struct Solver {
 }
 
 impl Solver {
-    fn new(problem: &Day15) -> Solver {
+    pub(crate) fn new(problem: &Day15) -> Solver {
         let mut frontier: PriorityQueue<(usize, usize, usize), usize> = PriorityQueue::new();
         frontier.push((0, 0, 0), usize::MAX-0);
 
@@ -82,17 +107,18 @@ impl Solver {
         Solver { frontier, total_risk, risk, max_x: problem.max_x, max_y: problem.max_y, goal_x, goal_y }
     }
 
-    fn new_augmented(problem: &Day15) -> Solver {
+    // Solver for the map tiled tiles x tiles times, risk increasing by one per tile.
+    pub(crate) fn new_augmented(problem: &Day15, tiles: usize) -> Solver {
         let mut frontier: PriorityQueue<(usize, usize, usize), usize> = PriorityQueue::new();
         frontier.push((0, 0, 0), usize::MAX-0);
 
         let mut risk= Vec::new();
         let mut total_risk = Vec::new();
-        for y_iter in 0..5 {
+        for y_iter in 0..tiles {
             for y in 0..=problem.max_y {
                 let mut risk_row: Vec<usize> = Vec::new();
                 let mut total_risk_row: Vec<usize> = Vec::new();
-                for x_iter in 0..5 {
+                for x_iter in 0..tiles {
                     for x in 0..=problem.max_x {
                         let mut r = *problem.risk.get(&(x, y)).unwrap();
                         r = ((r + y_iter + x_iter - 1) % 9) + 1;
@@ -104,15 +130,15 @@ impl Solver {
                 total_risk.push(total_risk_row);
             }
         }
-        let goal_x = (problem.max_x+1)*5-1;
-        let goal_y = (problem.max_y+1)*5-1;
+        let goal_x = (problem.max_x+1)*tiles-1;
+        let goal_y = (problem.max_y+1)*tiles-1;
         let max_x = goal_x;
         let max_y = goal_y;
 
         Solver { frontier, total_risk: total_risk, risk: risk, max_x, max_y, goal_x, goal_y }
     }
 
-    fn run(&mut self) -> usize {
+    pub(crate) fn run(&mut self) -> usize {
         while !self.frontier.is_empty() && (self.total_risk[self.goal_y][self.goal_x] == 0) {
             let ((x, y, total_risk), _priority) = self.frontier.pop().unwrap();
 
@@ -150,10 +176,25 @@ impl Day for Day15 {
 
     fn part2(&self) -> Result<usize, &str> {
 
-        let mut solver: Solver = Solver::new_augmented(self);
+        let mut solver: Solver = Solver::new_augmented(self, self.tiles.unwrap_or(TILES));
 
         Ok(solver.run())
     }
+
+    fn params(&self) -> Vec<Param> {
+        PARAMS.to_vec()
+    }
+
+    fn set_param(&mut self, name: &str, value: usize) -> Result<(), String> {
+        match name {
+            "tiles" if value == 0 => Err(String::from("tiles must be at least 1")),
+            "tiles" => {
+                self.tiles = Some(value);
+                Ok(())
+            }
+            _ => Err(format!("unknown parameter: {}", name)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -170,6 +211,18 @@ mod tests {
         assert_eq!(d.max_y, 9);
     }
 
+    #[test]
+    fn test_parse() {
+        let d = Day15::parse("116\n138\n").unwrap();
+        assert_eq!((d.max_x, d.max_y), (2, 1));
+        assert_eq!(d.part1(), Ok(12));
+        assert_eq!(Day15::parse("").err(), Some(String::from("empty map")));
+        assert_eq!(Day15::parse("116\n13\n").err(),
+                   Some(String::from("line 2 is not as wide as the first")));
+        assert_eq!(Day15::parse("116\n1x8\n").err(),
+                   Some(String::from("line 2: 'x' is not a risk level")));
+    }
+
     #[test]
     fn test_run() {
         let d = Day15::load("data/day15_example1.txt");
@@ -180,7 +233,7 @@ mod tests {
     #[test]
     fn test_run2() {
         let d = Day15::load("data/day15_example1.txt");
-        let mut solver = Solver::new_augmented(&d);
+        let mut solver = Solver::new_augmented(&d, 5);
         assert_eq!(solver.run(), 315);
     }
 
@@ -1,8 +1,9 @@
 use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use lazy_static::lazy_static;
-use regex::Regex;
+use std::fs;
+
+// Most operator packets one packet can be inside.  Each level is a recursive call, and one
+// costs as little as 18 bits, so a long message could otherwise overflow the stack.
+const MAX_NESTING: usize = 256;
 
 pub struct Day16 {
     message: String,
@@ -10,231 +11,138 @@ pub struct Day16 {
 
 impl Day16 {
     pub fn load(filename: &str) -> Day16 {
-        let mut line: String = String::new();
-        lazy_static! {
-            static ref LINE_RE: Regex =
-                Regex::new("([0-9]+)").unwrap();
-        }
+        Day16::load_str(&fs::read_to_string(filename).unwrap())
+    }
 
-        let file = File::open(filename).unwrap();
-        let mut reader = BufReader::new(file);
+    pub fn load_str(input: &str) -> Day16 {
+        Day16::parse(input).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        reader.read_line(&mut line).unwrap();
-        let message = line.trim_end();
+    // The message is the first line, in hex.
+    pub fn parse(input: &str) -> Result<Day16, String> {
+        let message = input.lines().next().unwrap_or("").trim_end();
+        if let Some(c) = message.chars().find(|c| !matches!(c, '0'..='9' | 'A'..='F')) {
+            return Err(format!("'{}' is not an upper case hex digit", c));
+        }
 
-        Day16 { message: message.to_string() }
+        Ok(Day16 { message: message.to_string() })
     }
 
     pub fn to_bits(&self) -> Vec<bool> {
         let mut bits = Vec::new();
         for c in self.message.chars() {
-            let new_bits = match c {
-                '0' => [false, false, false, false],
-                '1' => [false, false, false,  true],
-                '2' => [false, false,  true, false],
-                '3' => [false, false,  true,  true],
-                '4' => [false,  true, false, false],
-                '5' => [false,  true, false,  true],
-                '6' => [false,  true,  true, false],
-                '7' => [false,  true,  true,  true],
-                '8' => [ true, false, false, false],
-                '9' => [ true, false, false,  true],
-                'A' => [ true, false,  true, false],
-                'B' => [ true, false,  true,  true],
-                'C' => [ true,  true, false, false],
-                'D' => [ true,  true, false,  true],
-                'E' => [ true,  true,  true, false],
-                'F' => [ true,  true,  true,  true],
-                _ => { panic!() }
-            };
-            new_bits.map(|b| bits.push(b));
+            // parse() only lets hex digits through.
+            let digit = c.to_digit(16).unwrap();
+            for shift in (0..4).rev() {
+                bits.push(digit >> shift & 1 == 1);
+            }
         }
 
         bits
     }
 
-    // Scans one packet, returns (bits consumed, sum of versions)
-    fn scan_packet(&self, bits: &[bool]) -> (isize, usize, usize) {
-        // First three bits are version id
+    // Reads the next n bits as a number, if there are that many left.
+    fn read_bits(bits: &[bool], cursor: &mut usize, n: usize) -> Result<usize, &'static str> {
+        let chunk = bits.get(*cursor..*cursor + n).ok_or("packet is cut short")?;
+        *cursor += n;
 
-        let mut ver: usize = 0;
-        let mut sum: usize = ver;
-        let mut value: isize;
+        Ok(chunk.iter().fold(0, |acc, b| acc * 2 + *b as usize))
+    }
 
-        let mut cursor = 0;
-        for _ in 0..3 {
-            ver *= 2;
-            if bits[cursor] {
-                ver += 1;
-            }
-            cursor += 1;
-        }
-        sum += ver;
-        // println!("Ver = {}", ver);
-
-        let mut id: usize = 0;
-        for _ in 0..3 {
-            id *= 2;
-            if bits[cursor] {
-                id += 1;
-            }
-            cursor += 1;
+    // Scans one packet, returns (value, bits consumed, sum of versions), or what's wrong with
+    // the packet.
+    pub(crate) fn scan_packet(&self, bits: &[bool]) -> Result<(isize, usize, usize), &'static str> {
+        self.scan_nested(bits, 0)
+    }
+
+    // scan_packet() for a packet inside depth others.
+    fn scan_nested(&self, bits: &[bool], depth: usize)
+                   -> Result<(isize, usize, usize), &'static str> {
+        const OVERFLOW: &str = "packet value overflows";
+        if depth > MAX_NESTING {
+            return Err("packets are nested too deeply");
         }
-        // println!("id = {} ", id);
+        let mut cursor = 0;
 
-        if id == 4 {
+        // First three bits are version id, the next three the type id
+        let mut sum = Day16::read_bits(bits, &mut cursor, 3)?;
+        let id = Day16::read_bits(bits, &mut cursor, 3)?;
+
+        let value = if id == 4 {
             // literal.  Value is encoded in chunks of 5 bits
-            let mut literal_value: usize = 0;
+            let mut literal_value: isize = 0;
             let mut non_terminal = true;
             while non_terminal {
-                non_terminal = bits[cursor];
-                cursor += 1;
-                for _ in 0..4 {
-                    literal_value *= 2;
-                    if bits[cursor] {
-                        literal_value += 1;
-                    }
-                    cursor += 1;
-                }
+                non_terminal = Day16::read_bits(bits, &mut cursor, 1)? == 1;
+                let chunk = Day16::read_bits(bits, &mut cursor, 4)? as isize;
+                literal_value = literal_value.checked_mul(16)
+                    .and_then(|v| v.checked_add(chunk))
+                    .ok_or(OVERFLOW)?;
             }
 
-            value = literal_value as isize;
-            // println!("literal: {}", _literal_value);
+            literal_value
         }
         else {
             // non-literal.
             let mut sub_values: Vec<isize> = Vec::new();
 
-            let i = bits[cursor];
-            cursor += 1;
-            if i {
+            if Day16::read_bits(bits, &mut cursor, 1)? == 1 {
                 // next 11 bits are number of sub-packets contained in this one.
-                let mut len = 0;
-                for _ in 0..11 {
-                    len *= 2;
-                    if bits[cursor] {
-                        len += 1;
-                    }
-                    cursor += 1;
-                }
-                // println!("number of sub-packets: {}", len);
+                let len = Day16::read_bits(bits, &mut cursor, 11)?;
 
                 // Process <len> subpackets
                 for _ in 0..len {
-                    // println!("----------------");
-                    let (value, consumed, ver_sum) = self.scan_packet(&bits[cursor..]);
+                    let (value, consumed, ver_sum) = self.scan_nested(&bits[cursor..], depth + 1)?;
                     sub_values.push(value);
                     cursor += consumed;
                     sum += ver_sum;
                 }
-                // println!("----------------");
             }
             else {
                 // next 15 bits are total length in bits of subpackets of this one.
-                let mut len = 0;
-                for _ in 0..15 {
-                    len *= 2;
-                    if bits[cursor] {
-                        len += 1;
-                    }
-                    cursor += 1;
-                }
-                // println!("{} bits of subpackets", len);
+                let mut len = Day16::read_bits(bits, &mut cursor, 15)?;
 
                 // Process <len> bits of subpackets
                 while len > 0 {
-                    // println!("======================");
-                    let (value, consumed, sum_ver) = self.scan_packet(&bits[cursor..]);
+                    let (value, consumed, sum_ver) = self.scan_nested(&bits[cursor..], depth + 1)?;
                     sub_values.push(value);
                     cursor += consumed;
-                    len -= consumed;
+                    len = len.checked_sub(consumed).ok_or("sub-packets overrun their length")?;
                     sum += sum_ver;
                 }
-                // println!("===========================");
+            }
+
+            if sub_values.is_empty() {
+                return Err("operator packet has no sub-packets");
+            }
+            if id >= 5 && sub_values.len() != 2 {
+                return Err("comparison packet needs two sub-packets");
             }
 
             // compute value based on packet id and sub_values
-            value = match id {
-                0 => {
-                    // Sum
-                    value = 0;
-                    for x in sub_values {
-                        value += x;
-                    }
-
-                    value
-                }
-                1 => {
-                    // Product
-                    value = 1;
-                    for x in sub_values {
-                        value *= x;
-                    }
-
-                    value
-                }
-                2 => {
-                    // Minimum
-                    value = sub_values[0];
-                    for x in sub_values {
-                        if x < value {
-                            value = x;
-                        }
-                    }
-
-                    value
-                }
-                3 => {
-                    // Maximum
-                    value = sub_values[0];
-                    for x in sub_values {
-                        if x > value {
-                            value = x;
-                        }
-                    }
-
-                    value
-                }
-                4 => {
-                    // Literal (won't hit this case)
-                    panic!();
-                }
-                5 => {
-                    // Greater than
-                    value = 0;
-                    if sub_values[0] > sub_values[1] {
-                        value = 1;
-                    }
-
-                    value
-                }
-                6 => {
-                    // Less than
-                    value = 0;
-                    if sub_values[0] < sub_values[1] {
-                        value = 1;
-                    }
-
-                    value
-                }
-                7 => {
-                    // Equal To
-                    value = 0;
-                    if sub_values[0] == sub_values[1] {
-                        value = 1;
-                    }
-
-                    value
-                }
-                _ => {
-                    // Anything else is illegal
-                    panic!()
-                }
+            match id {
+                // Sum
+                0 => sub_values.iter().try_fold(0, |acc: isize, x| acc.checked_add(*x))
+                    .ok_or(OVERFLOW)?,
+                // Product
+                1 => sub_values.iter().try_fold(1, |acc: isize, x| acc.checked_mul(*x))
+                    .ok_or(OVERFLOW)?,
+                // Minimum
+                2 => *sub_values.iter().min().unwrap(),
+                // Maximum
+                3 => *sub_values.iter().max().unwrap(),
+                // Greater than
+                5 => (sub_values[0] > sub_values[1]) as isize,
+                // Less than
+                6 => (sub_values[0] < sub_values[1]) as isize,
+                // Equal To
+                7 => (sub_values[0] == sub_values[1]) as isize,
+                // Literals are handled above, and ids are only three bits
+                _ => unreachable!(),
             }
-        }
+        };
 
-        // println!("Scanned {}, sum = {}", cursor, sum);
-        (value, cursor, sum)
+        Ok((value, cursor, sum))
     }
 
 }
@@ -242,14 +150,14 @@ impl Day16 {
 impl Day for Day16 {
     fn part1(&self) -> Result<usize, &str> {
         let bits = self.to_bits();
-        let (_value, _consumed, sum_ver) = self.scan_packet(&bits);
+        let (_value, _consumed, sum_ver) = self.scan_packet(&bits)?;
 
         Ok(sum_ver)
     }
 
     fn part2(&self) -> Result<usize, &str> {
         let bits = self.to_bits();
-        let (value, _consumed, _sum_ver) = self.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = self.scan_packet(&bits)?;
 
         Ok(value as usize)
     }
@@ -285,19 +193,19 @@ mod tests {
     fn test_scan() {
         let d = Day16::load("data/day16_example1.txt");
         let bits = d.to_bits();
-        let (_value, _consumed, sum_ver) = d.scan_packet(&bits);
+        let (_value, _consumed, sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(sum_ver, 6);
 
         println!("------");
         let d = Day16::load("data/day16_example2.txt");
         let bits = d.to_bits();
-        let (_value, _consumed, sum_ver) = d.scan_packet(&bits);
+        let (_value, _consumed, sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(sum_ver, 9);
 
         println!("------");
         let d = Day16::load("data/day16_example7.txt");
         let bits = d.to_bits();
-        let (_value, _consumed, sum_ver) = d.scan_packet(&bits);
+        let (_value, _consumed, sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(sum_ver, 31);
     }
 
@@ -305,45 +213,79 @@ mod tests {
     fn test_scan_value() {
         let d = Day16::load("data/day16_example8.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 3);
 
         let d = Day16::load("data/day16_example9.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 54);
 
         let d = Day16::load("data/day16_example10.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 7);
 
         let d = Day16::load("data/day16_example11.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 9);
 
         let d = Day16::load("data/day16_example12.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 1);
 
         let d = Day16::load("data/day16_example13.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 0);
 
         let d = Day16::load("data/day16_example14.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 0);
 
         let d = Day16::load("data/day16_example15.txt");
         let bits = d.to_bits();
-        let (value, _consumed, _sum_ver) = d.scan_packet(&bits);
+        let (value, _consumed, _sum_ver) = d.scan_packet(&bits).unwrap();
         assert_eq!(value, 1);
     }
 
+    #[test]
+    fn test_bad_packets() {
+        assert!(Day16::parse("D2FE2G").is_err());
+        assert!(Day16::parse("d2fe28").is_err());
+        let scan = |message: &str| {
+            let d = Day16::parse(message).unwrap();
+            d.scan_packet(&d.to_bits())
+        };
+        assert_eq!(scan("D2FE28").map(|(value, ..)| value), Ok(2021));
+        assert_eq!(scan(""), Err("packet is cut short"));
+        assert_eq!(scan("D2FE"), Err("packet is cut short"));
+        // A sum whose 15 bit length is 0, so no sub-packets.
+        assert_eq!(scan("0000000"), Err("operator packet has no sub-packets"));
+        // A literal of 17 chunks, 16 of them 0xF, overflows.
+        assert_eq!(scan("13FFFFFFFFFFFFFFFFFFFDE"), Err("packet value overflows"));
+        assert_eq!(Day16::parse("").unwrap().part1(), Err("packet is cut short"));
+
+        // Sums of one sub-packet each around a literal 1.
+        let nested = |depth: usize| {
+            let mut bits = "000000100000000001".repeat(depth) + "00010000001";
+            while !bits.len().is_multiple_of(4) {
+                bits.push('0');
+            }
+            let digit = |chunk: &[u8]| {
+                let n = chunk.iter().fold(0, |acc, b| acc * 2 + (b - b'0') as u32);
+                std::char::from_digit(n, 16).unwrap().to_ascii_uppercase()
+            };
+            bits.as_bytes().chunks(4).map(digit).collect::<String>()
+        };
+        assert_eq!(scan(&nested(MAX_NESTING)).map(|(value, ..)| value), Ok(1));
+        assert_eq!(scan(&nested(MAX_NESTING + 1)), Err("packets are nested too deeply"));
+        assert_eq!(scan(&nested(100_000)), Err("packets are nested too deeply"));
+    }
+
     #[test]
     fn test_part1() {
         let d = Day16::load("data/day16_example7.txt");
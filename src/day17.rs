@@ -1,6 +1,5 @@
 use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
 use lazy_static::lazy_static;
 use regex::Regex;
 
@@ -17,6 +16,10 @@ impl Day17 {
     }
 
     pub fn load(filename: &str) -> Day17 {
+        Day17::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day17 {
         lazy_static! {
             static ref LINE_RE: Regex =
                 // Takes, e.g., 'target area: x=236..262, y=-78..-58'
@@ -25,15 +28,12 @@ impl Day17 {
                 Regex::new("target area: x=(-?[0-9]+)..(-?[0-9]+), y=(-?[0-9]+)..(-?[0-9]+)").unwrap();
         }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
         let mut x_min = 0;
         let mut x_max = 0;
         let mut y_min = 0;
         let mut y_max = 0;
 
-        for line in reader.lines() {
-            let l = &line.unwrap();
+        for l in input.lines() {
             let caps = LINE_RE.captures(&l);
             match caps {
                 Some(caps) => {
@@ -112,8 +112,10 @@ impl Day17 {
                 }
             }
 
-            // try reducing initial vy
-            vy -= 1;
+            if !hit {
+                // try reducing initial vy
+                vy -= 1;
+            }
         }
 
         (vx, vy, apex)
@@ -124,7 +126,7 @@ impl Day17 {
 
         let mut count = 0;
         for vx in 0..=self.x_max+1 {
-            for vy in self.y_min..=highest_vy+1 {
+            for vy in self.y_min..=highest_vy {
                 let (hit, _, _, _) = self.test_shot(vx, vy);
 
                 if hit {
@@ -147,6 +149,15 @@ impl Day for Day17 {
     fn part2(&self) -> Result<usize, &str> {
         Ok(self.num_shots())
     }
+
+    fn explain(&self) -> Vec<(String, String)> {
+        let (vx, vy, apex) = self.optimal_shot();
+
+        vec![
+            (String::from("optimal velocity"), format!("{},{}", vx, vy)),
+            (String::from("apex"), apex.to_string()),
+        ]
+    }
 }
 
 #[cfg(test)]
@@ -205,9 +216,9 @@ mod tests {
     #[test]
     fn test_optimize() {
         let d = Day17::new(20, 30, -10, -5);
-        let (_vx, _vy, apex) = d.optimal_shot();
-        // assert_eq!(vx, 7);
-        // assert_eq!(vy, 2);
+        let (vx, vy, apex) = d.optimal_shot();
+        assert_eq!(vx, 6);
+        assert_eq!(vy, 9);
         assert_eq!(apex, 45);
     }
 
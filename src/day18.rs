@@ -1,6 +1,6 @@
 use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
+use std::fmt;
 use std::vec;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -11,11 +11,11 @@ enum Elt {
 }
 
 #[derive(PartialEq, Debug, Clone)]
-struct Num {
+pub(crate) struct Num {
     elts : Option<Vec<Elt>>,
 }
 
-enum Operation {
+pub(crate) enum Operation {
     EXPLODE(usize, usize),        // elt_index, num_index
     SPLIT(usize, usize),          // index, value being split
     NOP,                          // No operation 
@@ -48,6 +48,32 @@ impl Num {
         }
     }
 
+    // Like parse(), but checks expr is a pair whose elements are single digits or pairs.
+    pub fn try_parse(expr: &str) -> Result<Num, String> {
+        // For each pair still open, whether its comma has been seen.
+        let mut open: Vec<bool> = Vec::new();
+        let mut expect_element = true;
+        for c in expr.chars().filter(|c| !c.is_whitespace()) {
+            match (expect_element, c) {
+                (true, '[') => open.push(false),
+                (true, '0'..='9') if !open.is_empty() => expect_element = false,
+                (false, ',') if open.last() == Some(&false) => {
+                    *open.last_mut().unwrap() = true;
+                    expect_element = true;
+                }
+                (false, ']') if open.last() == Some(&true) => {
+                    open.pop();
+                }
+                _ => return Err(format!("unexpected '{}' in '{}'", c, expr)),
+            }
+        }
+        if expect_element || !open.is_empty() {
+            return Err(format!("'{}' is not a complete pair", expr));
+        }
+
+        Ok(Num::parse(expr))
+    }
+
     fn print_num(&self) {
         match &self.elts {
             None => {print!("NONE")}
@@ -374,23 +400,61 @@ impl Num {
 
 }
 
+impl fmt::Display for Num {
+    // Writes the number back out in puzzle notation, e.g. "[[1,2],3]".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.elts {
+            None => write!(f, "0"),
+            Some(v) => {
+                let mut need_comma = false;
+                for e in v {
+                    match e {
+                        Elt::LEFT => {
+                            if need_comma { write!(f, ",")?; }
+                            write!(f, "[")?;
+                            need_comma = false;
+                        }
+                        Elt::RIGHT => {
+                            write!(f, "]")?;
+                            need_comma = true;
+                        }
+                        Elt::VALUE(n) => {
+                            if need_comma { write!(f, ",")?; }
+                            write!(f, "{}", n)?;
+                            need_comma = true;
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 pub struct Day18 {
-    numbers: Vec<Num>,
+    pub(crate) numbers: Vec<Num>,
 }
 
 impl Day18 {
     pub fn load(filename: &str) -> Day18 {
-        let mut numbers: Vec<Num> = Vec::new();
+        Day18::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day18 {
+        Day18::parse(input).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+    // One number per line, blank lines are skipped.
+    pub fn parse(input: &str) -> Result<Day18, String> {
+        let mut numbers: Vec<Num> = Vec::new();
 
-        for line in reader.lines() {
-            let l = &line.unwrap();
-            numbers.push(Num::parse(l));
+        for (n, l) in input.lines().enumerate() {
+            if !l.trim().is_empty() {
+                numbers.push(Num::try_parse(l).map_err(|e| format!("line {}: {}", n + 1, e))?);
+            }
         }
 
-        Day18 { numbers }
+        Ok(Day18 { numbers })
     }
 }
 
@@ -472,6 +536,25 @@ mod tests {
         assert_eq!(n.magnitude(), 3*(3*1+2*2)+2*(3*8+2*9));
     }
 
+    #[test]
+    fn test_try_parse() {
+        let n = Num::try_parse("[[1, 2], [8,9]]").unwrap();
+        assert_eq!(n, Num::parse("[[1,2],[8,9]]"));
+        for bad in ["", "5", "[1", "[1,2", "[1,2]]", "[1,2],[3,4]", "[12,3]", "[1;2]", "[,1]",
+                    "[1,2,3]", "[[1,2]]"].iter() {
+            assert!(Num::try_parse(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(Day18::parse("[1,2]\n\n[3,4]\n").unwrap().numbers.len(), 2);
+        assert_eq!(Day18::parse("[1,2]\n[3,x]").err(),
+                   Some(String::from("line 2: unexpected 'x' in '[3,x]'")));
+    }
+
+    #[test]
+    fn test_display() {
+        let s = "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]";
+        assert_eq!(Num::parse(s).to_string(), s);
+        assert_eq!(Num::zero().to_string(), "0");
+    }
 
     #[test]
     fn test_explode() {
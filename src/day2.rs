@@ -1,119 +1,804 @@
+// Submarine course scripts.
+//
+// Besides plain `forward|up|down|left|right <n>` lines a course may use:
+//   // comment                  ignored to the end of the line
+//   repeat N { ... }            runs the block N times, blocks can nest
+//   macro name { ... }          defines a named block, later lines run it with `name`
+//   #include "other.txt"        splices in another course file, relative to this one
+//
+// Scripts are expanded into a flat list of commands when loaded, both parts interpret that.
+
 use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Expanded courses longer than this are rejected, a few nested repeats could exhaust memory.
+const MAX_COMMANDS: usize = 1_000_000;
 
-enum Dir {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dir {
     FORWARD,
     UP,
     DOWN,
+    LEFT,
+    RIGHT,
 }
 
-struct Command {
-    dir: Dir,
-    dist: usize,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Command {
+    pub dir: Dir,
+    pub dist: usize,
 }
 
-pub struct Day2 {
-    commands: Vec<Command>,
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let word = match self.dir {
+            Dir::FORWARD => "forward",
+            Dir::UP => "up",
+            Dir::DOWN => "down",
+            Dir::LEFT => "left",
+            Dir::RIGHT => "right",
+        };
+        write!(f, "{} {}", word, self.dist)
+    }
 }
 
-impl Day2 {
-    pub fn load(filename: &str) -> Day2 {
-        let mut commands: Vec<Command> = Vec::new();
-
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = &line.unwrap();
-            let parts = l.split(" ").collect::<Vec<&str>>();
-            if parts.len() == 2 {
-                // we have the two parts
-                let mut command = Command {
-                    dir: Dir::FORWARD,
-                    dist: 0,
-                };
-                match parts[0] {
-                    "forward" => {
-                        command.dir = Dir::FORWARD;
-                    }
-                    "down" => {
-                        command.dir = Dir::DOWN;
-                    }
-                    "up" => {
-                        command.dir = Dir::UP;
-                    }
-                    _ => {
-                        panic!();
-                    }
+// Where the submarine is.  Depth grows downwards, lateral grows to starboard (right).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    pub forward: i64,
+    pub depth: i64,
+    pub aim: i64,
+    pub lateral: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NavErrorKind {
+    Surfaced,
+    Overflow,
+    Unsupported,
+}
+
+impl NavErrorKind {
+    fn describe(&self) -> &'static str {
+        match self {
+            NavErrorKind::Surfaced => "course goes above the surface",
+            NavErrorKind::Overflow => "position overflows",
+            NavErrorKind::Unsupported => "command not supported by this navigation model",
+        }
+    }
+}
+
+// A course that can't be followed, index is the 0-based position in the expanded course.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NavError {
+    pub index: usize,
+    pub command: Command,
+    pub kind: NavErrorKind,
+}
+
+impl fmt::Display for NavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "command {} ({}): {}", self.index, self.command, self.kind.describe())
+    }
+}
+
+// How one command moves the submarine.  Surfacing is checked by Day2::navigate(), models
+// only report arithmetic overflow and commands they don't understand.
+pub trait NavigationModel {
+    fn step(&self, pos: Position, cmd: &Command) -> Result<Position, NavErrorKind>;
+}
+
+fn signed(dist: usize) -> Result<i64, NavErrorKind> {
+    i64::try_from(dist).map_err(|_| NavErrorKind::Overflow)
+}
+
+fn add(a: i64, b: i64) -> Result<i64, NavErrorKind> {
+    a.checked_add(b).ok_or(NavErrorKind::Overflow)
+}
+
+fn sub(a: i64, b: i64) -> Result<i64, NavErrorKind> {
+    a.checked_sub(b).ok_or(NavErrorKind::Overflow)
+}
+
+// Looks up a navigation model by the name used on the command line.
+pub fn model_by_name(name: &str) -> Result<Box<dyn NavigationModel>, String> {
+    match name {
+        "direct" => Ok(Box::new(DirectModel)),
+        "aim" => Ok(Box::new(AimModel)),
+        "lateral" => Ok(Box::new(LateralModel)),
+        _ => Err(format!("unknown navigation model \"{}\" (direct, aim or lateral)", name)),
+    }
+}
+
+// Part 1: up and down change depth directly.
+pub struct DirectModel;
+
+impl NavigationModel for DirectModel {
+    fn step(&self, mut pos: Position, cmd: &Command) -> Result<Position, NavErrorKind> {
+        let dist = signed(cmd.dist)?;
+        match cmd.dir {
+            Dir::FORWARD => pos.forward = add(pos.forward, dist)?,
+            Dir::DOWN => pos.depth = add(pos.depth, dist)?,
+            Dir::UP => pos.depth = sub(pos.depth, dist)?,
+            Dir::LEFT | Dir::RIGHT => return Err(NavErrorKind::Unsupported),
+        }
+
+        Ok(pos)
+    }
+}
+
+// Part 2: up and down change aim, forward dives by aim times the distance.
+pub struct AimModel;
+
+impl NavigationModel for AimModel {
+    fn step(&self, mut pos: Position, cmd: &Command) -> Result<Position, NavErrorKind> {
+        let dist = signed(cmd.dist)?;
+        match cmd.dir {
+            Dir::FORWARD => {
+                pos.forward = add(pos.forward, dist)?;
+                let dive = pos.aim.checked_mul(dist).ok_or(NavErrorKind::Overflow)?;
+                pos.depth = add(pos.depth, dive)?;
+            }
+            Dir::DOWN => pos.aim = add(pos.aim, dist)?,
+            Dir::UP => pos.aim = sub(pos.aim, dist)?,
+            Dir::LEFT | Dir::RIGHT => return Err(NavErrorKind::Unsupported),
+        }
+
+        Ok(pos)
+    }
+}
+
+// The aim model plus a lateral axis: left and right move the submarine sideways.
+pub struct LateralModel;
+
+impl NavigationModel for LateralModel {
+    fn step(&self, mut pos: Position, cmd: &Command) -> Result<Position, NavErrorKind> {
+        let dist = signed(cmd.dist)?;
+        match cmd.dir {
+            Dir::LEFT => pos.lateral = sub(pos.lateral, dist)?,
+            Dir::RIGHT => pos.lateral = add(pos.lateral, dist)?,
+            _ => return AimModel.step(pos, cmd),
+        }
+
+        Ok(pos)
+    }
+}
+
+// A course script error, with the file (None for a script given as a string) and 1-based line
+// (None if the file couldn't be read at all).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: {}", file.display(), line, self.msg),
+            (Some(file), None) => write!(f, "{}: {}", file.display(), self.msg),
+            (None, Some(line)) => write!(f, "line {}: {}", line, self.msg),
+            (None, None) => write!(f, "{}", self.msg),
+        }
+    }
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+// Splits a script into words and braces, dropping comments.  `#include` takes the rest of
+// its line as the file name so names with spaces work when quoted.
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (n, l) in input.lines().enumerate() {
+        let line = n + 1;
+        let l = match l.find("//") {
+            Some(pos) => &l[..pos],
+            None => l,
+        };
+        let l = l.trim();
+
+        if let Some(rest) = l.strip_prefix("#include") {
+            tokens.push(Token { text: String::from("#include"), line });
+            let name = rest.trim().trim_matches('"');
+            if !name.is_empty() {
+                tokens.push(Token { text: name.to_string(), line });
+            }
+            continue;
+        }
+
+        for word in l.replace('{', " { ").replace('}', " } ").split_whitespace() {
+            tokens.push(Token { text: word.to_string(), line });
+        }
+    }
+
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    file: Option<PathBuf>,
+    // Shared with included files, so macros defined in one can be used in another.
+    macros: &'a mut HashMap<String, Vec<Command>>,
+    // Files currently being parsed, to catch include cycles.
+    including: &'a mut Vec<PathBuf>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, line: usize, msg: String) -> ParseError {
+        ParseError { file: self.file.clone(), line: Some(line), msg }
+    }
+
+    // Line of the current token, or of the last one at end of input.
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some(token) => token.line,
+            None => 1,
+        }
+    }
+
+    fn next(&mut self) -> Option<(String, usize)> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some((token.text.clone(), token.line))
+    }
+
+    fn number(&mut self, after: &str) -> Result<usize, ParseError> {
+        let line = self.line();
+        match self.next() {
+            Some((text, line)) => text.parse::<usize>().map_err(|_| {
+                self.error(line, format!("expected a number after '{}', found '{}'", after, text))
+            }),
+            None => Err(self.error(line, format!("expected a number after '{}'", after))),
+        }
+    }
+
+    fn open_brace(&mut self, after: &str) -> Result<(), ParseError> {
+        let line = self.line();
+        match self.next() {
+            Some((text, _)) if text == "{" => Ok(()),
+            Some((text, line)) => {
+                Err(self.error(line, format!("expected '{{' after '{}', found '{}'", after, text)))
+            }
+            None => Err(self.error(line, format!("expected '{{' after '{}'", after))),
+        }
+    }
+
+    fn extend(&self, out: &mut Vec<Command>, cmds: &[Command], times: usize, line: usize)
+              -> Result<(), ParseError> {
+        // Repeating nothing adds nothing, however many times.
+        if cmds.is_empty() {
+            return Ok(());
+        }
+        let total = cmds.len().checked_mul(times).and_then(|n| n.checked_add(out.len()));
+        match total {
+            Some(total) if total <= MAX_COMMANDS => {
+                for _ in 0..times {
+                    out.extend_from_slice(cmds);
                 }
-                command.dist = parts[1].parse::<usize>().unwrap();
-                commands.push(command);
+                Ok(())
             }
+            _ => Err(self.error(line, format!("course expands to more than {} commands",
+                                              MAX_COMMANDS))),
+        }
+    }
+
+    fn include(&mut self, name: &str, line: usize) -> Result<Vec<Command>, ParseError> {
+        let base = self.file.as_ref().and_then(|f| f.parent()).unwrap_or_else(|| Path::new(""));
+        let path = base.join(name);
+        let input = fs::read_to_string(&path)
+            .map_err(|e| self.error(line, format!("can't include {}: {}", path.display(), e)))?;
+
+        let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.including.contains(&key) {
+            return Err(self.error(line, format!("{} includes itself", path.display())));
         }
 
-        Day2 { commands }
+        self.including.push(key);
+        let mut parser = Parser {
+            tokens: tokenize(&input),
+            pos: 0,
+            file: Some(path),
+            macros: self.macros,
+            including: self.including,
+        };
+        let result = parser.block(None);
+        self.including.pop();
+
+        result
     }
 
-    fn follow_course(&self, mut pos: (usize, usize)) -> (usize, usize) {
-        for cmd in &self.commands {
-            match &cmd.dir {
-                Dir::FORWARD => {
-                    pos.0 = pos.0 + cmd.dist;
-                } // increase fwd pos
-                Dir::DOWN => {
-                    pos.1 = pos.1 + cmd.dist;
-                } // increase depth
-                Dir::UP => {
-                    pos.1 = pos.1 - cmd.dist;
-                } // decrease depth
+    // Parses statements up to the '}' closing a block opened on line `opened`, or to the end
+    // of input for the top level, and returns them expanded.
+    fn block(&mut self, opened: Option<usize>) -> Result<Vec<Command>, ParseError> {
+        let mut out: Vec<Command> = Vec::new();
+
+        loop {
+            let (word, line) = match (self.next(), opened) {
+                (Some(token), _) => token,
+                (None, None) => return Ok(out),
+                (None, Some(opened)) => {
+                    let line = self.line();
+                    return Err(self.error(line,
+                        format!("missing '}}' for block opened on line {}", opened)));
+                }
+            };
+
+            match word.as_str() {
+                "}" => match opened {
+                    Some(_) => return Ok(out),
+                    None => return Err(self.error(line, String::from("unexpected '}'"))),
+                },
+                "forward" | "down" | "up" | "left" | "right" => {
+                    let dir = match word.as_str() {
+                        "forward" => Dir::FORWARD,
+                        "down" => Dir::DOWN,
+                        "up" => Dir::UP,
+                        "left" => Dir::LEFT,
+                        _ => Dir::RIGHT,
+                    };
+                    let dist = self.number(&word)?;
+                    self.extend(&mut out, &[Command { dir, dist }], 1, line)?;
+                }
+                "repeat" => {
+                    let times = self.number("repeat")?;
+                    self.open_brace("repeat")?;
+                    let body = self.block(Some(line))?;
+                    self.extend(&mut out, &body, times, line)?;
+                }
+                "macro" => {
+                    let name = match self.next() {
+                        Some((name, _)) if is_name(&name) => name,
+                        Some((name, line)) => {
+                            return Err(self.error(line, format!("bad macro name '{}'", name)))
+                        }
+                        None => return Err(self.error(line, String::from("expected a macro name"))),
+                    };
+                    if self.macros.contains_key(&name) {
+                        return Err(self.error(line, format!("macro '{}' is already defined", name)));
+                    }
+                    self.open_brace(&name)?;
+                    let body = self.block(Some(line))?;
+                    self.macros.insert(name, body);
+                }
+                "#include" => {
+                    let name = match self.tokens.get(self.pos) {
+                        Some(token) if token.line == line => token.text.clone(),
+                        _ => return Err(self.error(line, String::from("expected a file to include"))),
+                    };
+                    self.pos += 1;
+                    let body = self.include(&name, line)?;
+                    self.extend(&mut out, &body, 1, line)?;
+                }
+                _ => match self.macros.get(&word) {
+                    Some(body) => {
+                        let body = body.clone();
+                        self.extend(&mut out, &body, 1, line)?;
+                    }
+                    None => return Err(self.error(line, format!("unknown command '{}'", word))),
+                },
             }
         }
+    }
+}
+
+// Macro names are identifiers that don't clash with the built-in words.
+fn is_name(name: &str) -> bool {
+    let keyword = ["forward", "down", "up", "left", "right", "repeat", "macro"].contains(&name);
+    let mut chars = name.chars();
+    let first = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+
+    !keyword && first && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Where a course went: the starting position, then the position after each command, so
+// positions[i + 1] is where commands[i] left the submarine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    pub commands: Vec<Command>,
+    pub positions: Vec<Position>,
+}
+
+const SVG_WIDTH: f64 = 800.0;
+const SVG_HEIGHT: f64 = 400.0;
+const SVG_MARGIN: f64 = 40.0;
+
+impl Trajectory {
+    // One row per position, the first row (the start) has no command.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("step,command,forward,depth,aim,lateral\n");
+        for (step, pos) in self.positions.iter().enumerate() {
+            let command = match step {
+                0 => String::new(),
+                _ => self.commands[step - 1].to_string(),
+            };
+            csv += &format!("{},{},{},{},{},{}\n",
+                            step, command, pos.forward, pos.depth, pos.aim, pos.lateral);
+        }
+
+        csv
+    }
+
+    // Depth against horizontal position, surface at the top, scaled to fit the plot.
+    pub fn to_svg(&self) -> String {
+        let max_forward = self.positions.iter().map(|p| p.forward).max().unwrap_or(0).max(1);
+        let max_depth = self.positions.iter().map(|p| p.depth).max().unwrap_or(0).max(1);
+        let x_scale = (SVG_WIDTH - 2.0 * SVG_MARGIN) / max_forward as f64;
+        let y_scale = (SVG_HEIGHT - 2.0 * SVG_MARGIN) / max_depth as f64;
+
+        let points: Vec<String> = self.positions.iter()
+            .map(|p| format!("{:.1},{:.1}",
+                             SVG_MARGIN + p.forward as f64 * x_scale,
+                             SVG_MARGIN + p.depth as f64 * y_scale))
+            .collect();
+
+        let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+                               viewBox=\"0 0 {w} {h}\">\n", w = SVG_WIDTH, h = SVG_HEIGHT);
+        svg += &format!("  <rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n", SVG_WIDTH, SVG_HEIGHT);
+        svg += &format!("  <line x1=\"{m}\" y1=\"{m}\" x2=\"{x}\" y2=\"{m}\" stroke=\"gray\" \
+                         stroke-dasharray=\"4\"/>\n", m = SVG_MARGIN, x = SVG_WIDTH - SVG_MARGIN);
+        svg += &format!("  <polyline fill=\"none\" stroke=\"steelblue\" points=\"{}\"/>\n",
+                        points.join(" "));
+        svg += &format!("  <text x=\"{}\" y=\"{}\" font-size=\"12\">surface</text>\n",
+                        SVG_MARGIN, SVG_MARGIN - 8.0);
+        svg += &format!("  <text x=\"{}\" y=\"{}\" font-size=\"12\" text-anchor=\"end\">\
+                         forward {}</text>\n", SVG_WIDTH - SVG_MARGIN, SVG_MARGIN - 8.0, max_forward);
+        svg += &format!("  <text x=\"{}\" y=\"{}\" font-size=\"12\">depth {}</text>\n",
+                        SVG_MARGIN, SVG_HEIGHT - SVG_MARGIN + 20.0, max_depth);
+        svg += "</svg>\n";
+
+        svg
+    }
+}
+
+// Constraints on planned courses, None for no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlanLimits {
+    // Largest distance a single command may move or turn.
+    pub max_dist: Option<usize>,
+    // Largest aim the submarine may reach.
+    pub max_aim: Option<usize>,
+}
+
+// Why Day2::plan() has no course.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanError {
+    // The limits make the target unreachable.
+    Unreachable,
+    // The search ran out of steps before it could show which course is shortest.
+    GaveUp,
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::Unreachable => write!(f, "target is out of reach"),
+            PlanError::GaveUp => write!(f, "search limit reached before finding a course"),
+        }
+    }
+}
+
+// Search steps Day2::plan() takes before giving up, around a second in a release build.
+const PLAN_SEARCH_LIMIT: usize = 5_000_000;
+
+// Commands needed to cover dist when each may move at most max_dist.
+fn commands_for(dist: usize, max_dist: usize) -> usize {
+    dist.div_ceil(max_dist)
+}
+
+// Search behind Day2::plan().  A course is planned as legs: travel some distance at one aim,
+// then turn down to a steeper aim for the next leg.
+struct Planner {
+    max_dist: usize,
+    max_aim: usize,
+    // (aim, forward left, depth left, budget) already shown to have no course.
+    failed: HashSet<(usize, usize, usize, usize)>,
+    // Calls to search() so far, and how many it may make.
+    steps: usize,
+    limit: usize,
+}
+
+impl Planner {
+    // Fewest commands that could finish from this aim, None if nothing can.
+    fn lower_bound(&self, aim: usize, left: usize, deeper: usize) -> Option<usize> {
+        if left == 0 {
+            return if deeper == 0 { Some(0) } else { None };
+        }
+        if deeper < aim.saturating_mul(left) || deeper > self.max_aim.saturating_mul(left) {
+            return None;
+        }
+
+        // The steepest leg is at least the average aim still needed.
+        let steepest = deeper.div_ceil(left);
+        let turning = commands_for(steepest - aim, self.max_dist);
+        Some(commands_for(left, self.max_dist) + turning)
+    }
 
-        pos
+    fn gave_up(&self) -> bool {
+        self.steps > self.limit
     }
 
-    fn follow_course2(&self, mut pos: (isize, isize, isize)) -> (isize, isize, isize) {
-        // pos is (forward, depth, aim)
+    // Legs (aim, distance) finishing the course within budget commands.  None if there are
+    // none, or if the search gave up.
+    fn search(&mut self, aim: usize, left: usize, deeper: usize, budget: usize)
+              -> Option<Vec<(usize, usize)>> {
+        self.steps += 1;
+        if self.gave_up() || self.lower_bound(aim, left, deeper)? > budget {
+            return None;
+        }
+        if deeper == aim * left {
+            return Some(if left == 0 { vec![] } else { vec![(aim, left)] });
+        }
+        if self.failed.contains(&(aim, left, deeper, budget)) {
+            return None;
+        }
+
+        // Travel some way at this aim (not at all is only sensible from the start), then turn.
+        let first = if aim == 0 { 0 } else { 1 };
+        for dist in first..left {
+            let travel = commands_for(dist, self.max_dist);
+            let rest = left - dist;
+            let still = deeper - aim * dist;
+            let steepest = self.max_aim.min(still / rest);
+            if travel > budget || steepest <= aim {
+                continue;
+            }
+            let budget = budget - travel;
+
+            // The rest of the distance still has to be covered, and the aim turned to at
+            // least the average still needed, whatever comes next.
+            let cruise = commands_for(rest, self.max_dist);
+            let average = still.div_ceil(rest);
+            if cruise + commands_for(average - aim, self.max_dist) > budget {
+                continue;
+            }
+
+            // Two more legs take at least two turns, so a smaller budget has to finish with
+            // one, at exactly the aim that gives the depth left.  Otherwise the next aim can't
+            // be so shallow that the turns after it don't fit.
+            let next_aims = if budget < 2 + cruise {
+                if !still.is_multiple_of(rest) {
+                    continue;
+                }
+                average..=average
+            } else {
+                let reach = self.max_dist.saturating_mul(budget - cruise - 1);
+                (aim + 1).max(average.saturating_sub(reach))..=steepest
+            };
 
-        for cmd in &self.commands {
-            match &cmd.dir {
-                Dir::FORWARD => {
-                    pos.0 = pos.0 + cmd.dist as isize; // increase fwd pos
-                    pos.1 = pos.1 + (cmd.dist as isize) * pos.2; // change depth using aim.
+            for next in next_aims {
+                let turn = commands_for(next - aim, self.max_dist);
+                if turn > budget {
+                    break;
                 }
-                Dir::DOWN => {
-                    pos.2 = pos.2 + cmd.dist as isize; // change aim
+                if let Some(mut legs) = self.search(next, rest, still, budget - turn) {
+                    if dist > 0 {
+                        legs.insert(0, (aim, dist));
+                    }
+                    return Some(legs);
                 }
-                Dir::UP => {
-                    pos.2 = pos.2 - cmd.dist as isize; // change aim
+                if self.gave_up() {
+                    return None;
                 }
             }
         }
 
-        pos
+        self.failed.insert((aim, left, deeper, budget));
+        None
+    }
+}
+
+pub struct Day2 {
+    commands: Vec<Command>,
+    // Why each part failed, kept here so part1() and part2() can lend out the message.
+    failures: [OnceCell<String>; 2],
+}
+
+impl Day2 {
+    pub fn load(filename: &str) -> Day2 {
+        Day2::parse_file(Path::new(filename)).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn load_str(input: &str) -> Day2 {
+        Day2::parse(input).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Parses a course script, includes are relative to the current directory.
+    pub fn parse(input: &str) -> Result<Day2, ParseError> {
+        Day2::parse_script(input, None)
+    }
+
+    // Parses a course script file, includes are relative to the file.
+    pub fn parse_file(path: &Path) -> Result<Day2, ParseError> {
+        let input = fs::read_to_string(path).map_err(|e| ParseError {
+            file: Some(path.to_path_buf()),
+            line: None,
+            msg: format!("can't read: {}", e),
+        })?;
+
+        Day2::parse_script(&input, Some(path.to_path_buf()))
+    }
+
+    // A course made of the given commands, e.g. a plan.
+    pub fn from_commands(commands: Vec<Command>) -> Day2 {
+        Day2 { commands, failures: Default::default() }
+    }
+
+    // Finds a shortest course that ends at (forward, depth) under the aim model.
+    //
+    // Only position and depth at the end count, and depth is the sum of aim times distance
+    // over the forward moves, so the same moves in order of increasing aim end up in the same
+    // place with no more turning.  Plans therefore only turn down: the aim stays between 0
+    // and max_aim and the submarine never climbs.  The search tries ever longer courses, each
+    // as legs of increasing aim, until one fits.  That's quick when commands may be long, but
+    // with a small max_dist a large target has too many ways to fall just short; the search
+    // gives up after PLAN_SEARCH_LIMIT steps rather than run for minutes.
+    pub fn plan(forward: usize, depth: usize, limits: PlanLimits)
+                -> Result<Vec<Command>, PlanError> {
+        Day2::plan_within(forward, depth, limits, PLAN_SEARCH_LIMIT)
+    }
+
+    // plan() taking at most limit search steps.
+    fn plan_within(forward: usize, depth: usize, limits: PlanLimits, limit: usize)
+                   -> Result<Vec<Command>, PlanError> {
+        let max_dist = limits.max_dist.unwrap_or(usize::MAX);
+        let max_aim = limits.max_aim.unwrap_or(usize::MAX);
+        if max_dist == 0 && (forward, depth) != (0, 0) {
+            return Err(PlanError::Unreachable);
+        }
+
+        let mut planner = Planner {
+            max_dist: max_dist.max(1),
+            max_aim,
+            failed: HashSet::new(),
+            steps: 0,
+            limit,
+        };
+        // Going down at the floor of the average aim and then one steeper always works once
+        // the target is in range, so this terminates.
+        let mut budget = planner.lower_bound(0, forward, depth).ok_or(PlanError::Unreachable)?;
+        let legs = loop {
+            if let Some(legs) = planner.search(0, forward, depth, budget) {
+                break legs;
+            }
+            if planner.gave_up() {
+                return Err(PlanError::GaveUp);
+            }
+            budget += 1;
+        };
+
+        // Split turns and legs into commands of at most max_dist.
+        let mut commands = Vec::new();
+        let mut split = |dir: Dir, mut dist: usize| {
+            while dist > 0 {
+                let step = dist.min(max_dist);
+                commands.push(Command { dir, dist: step });
+                dist -= step;
+            }
+        };
+        let mut aim = 0;
+        for (leg_aim, dist) in legs {
+            split(Dir::DOWN, leg_aim - aim);
+            split(Dir::FORWARD, dist);
+            aim = leg_aim;
+        }
+
+        Ok(commands)
+    }
+
+    fn parse_script(input: &str, file: Option<PathBuf>) -> Result<Day2, ParseError> {
+        let mut macros = HashMap::new();
+        let mut including: Vec<PathBuf> = file.iter()
+            .map(|f| fs::canonicalize(f).unwrap_or_else(|_| f.clone()))
+            .collect();
+        let mut parser = Parser {
+            tokens: tokenize(input),
+            pos: 0,
+            file,
+            macros: &mut macros,
+            including: &mut including,
+        };
+        let commands = parser.block(None)?;
+
+        Ok(Day2 { commands, failures: Default::default() })
+    }
+
+    // Runs the course under a navigation model, from the surface.  Fails at the first command
+    // that takes the submarine above the surface, overflows or that the model can't follow.
+    pub fn navigate(&self, model: &dyn NavigationModel) -> Result<Position, NavError> {
+        self.walk(model, |_| {})
+    }
+
+    // Like navigate(), but keeps the position after every command.
+    pub fn trajectory(&self, model: &dyn NavigationModel) -> Result<Trajectory, NavError> {
+        let mut positions = vec![Position::default()];
+        self.walk(model, |pos| positions.push(pos))?;
+
+        Ok(Trajectory { commands: self.commands.clone(), positions })
+    }
+
+    fn walk<F: FnMut(Position)>(&self, model: &dyn NavigationModel, mut visit: F)
+                                -> Result<Position, NavError> {
+        let mut pos = Position::default();
+
+        for (index, cmd) in self.commands.iter().enumerate() {
+            let error = |kind| NavError { index, command: *cmd, kind };
+            pos = model.step(pos, cmd).map_err(error)?;
+            if pos.depth < 0 {
+                return Err(error(NavErrorKind::Surfaced));
+            }
+            visit(pos);
+        }
+
+        Ok(pos)
+    }
+
+    fn follow_course(&self) -> Result<Position, NavError> {
+        self.navigate(&DirectModel)
+    }
+
+    fn follow_course2(&self) -> Result<Position, NavError> {
+        self.navigate(&AimModel)
+    }
+}
+
+impl Day2 {
+    // Position times depth at the end of the course, or which command it failed on.
+    fn answer(&self, part: usize, course: Result<Position, NavError>) -> Result<usize, &str> {
+        let failure = &self.failures[part - 1];
+        let pos = course.map_err(|e| failure.get_or_init(|| e.to_string()).as_str())?;
+        pos.forward.checked_mul(pos.depth)
+            .and_then(|val| usize::try_from(val).ok())
+            .ok_or("answer overflows")
     }
 }
 
 impl Day for Day2 {
     fn part1(&self) -> Result<usize, &str> {
-        let mut pos: (usize, usize) = (0, 0);
-        pos = self.follow_course(pos);
-
-        Ok(pos.0 * pos.1)
+        self.answer(1, self.follow_course())
     }
 
     fn part2(&self) -> Result<usize, &str> {
-        let mut pos: (isize, isize, isize) = (0, 0, 0);
-        pos = self.follow_course2(pos);
+        self.answer(2, self.follow_course2())
+    }
+
+    fn explain(&self) -> Vec<(String, String)> {
+        let mut details = Vec::new();
+
+        match self.follow_course() {
+            Ok(pos) => {
+                details.push((String::from("part 1 position"), pos.forward.to_string()));
+                details.push((String::from("part 1 depth"), pos.depth.to_string()));
+            }
+            Err(e) => details.push((String::from("part 1 error"), e.to_string())),
+        }
+        match self.follow_course2() {
+            Ok(pos) => {
+                details.push((String::from("part 2 position"), pos.forward.to_string()));
+                details.push((String::from("part 2 depth"), pos.depth.to_string()));
+                details.push((String::from("part 2 aim"), pos.aim.to_string()));
+            }
+            Err(e) => details.push((String::from("part 2 error"), e.to_string())),
+        }
 
-        Ok((pos.0 * pos.1) as usize)
+        details
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::VecDeque;
     //use crate::day::Day;
 
     #[test]
@@ -125,20 +810,254 @@ mod tests {
     #[test]
     fn test_follow_course() {
         let d = Day2::load("data/day2_example1.txt");
-        let mut pos: (usize, usize) = (0, 0);
-        pos = d.follow_course(pos);
-        assert_eq!(pos.0, 15);
-        assert_eq!(pos.1, 10);
+        let pos = d.follow_course().unwrap();
+        assert_eq!(pos.forward, 15);
+        assert_eq!(pos.depth, 10);
     }
 
     #[test]
     fn test_follow_course2() {
         let d = Day2::load("data/day2_example1.txt");
-        let mut pos: (isize, isize, isize) = (0, 0, 0);
-        pos = d.follow_course2(pos);
-        assert_eq!(pos.0, 15);
-        assert_eq!(pos.1, 60);
-        assert_eq!(pos.2, 10); // final aim
+        let pos = d.follow_course2().unwrap();
+        assert_eq!(pos.forward, 15);
+        assert_eq!(pos.depth, 60);
+        assert_eq!(pos.aim, 10); // final aim
     }
 
+    #[test]
+    fn test_explain() {
+        let d = Day2::load_str("forward 5\ndown 5\nforward 8\nup 3\ndown 8\nforward 2\n");
+        let details = d.explain();
+        assert_eq!(details[0], (String::from("part 1 position"), String::from("15")));
+        assert_eq!(details[4], (String::from("part 2 aim"), String::from("10")));
+    }
+
+    #[test]
+    fn test_script() {
+        let script = "\
+// example course, in a loop
+macro dive { down 2 forward 1 }
+repeat 2 {
+    dive
+    forward 3   // cruise
+}
+up 1
+";
+        let d = Day2::load_str(script);
+        assert_eq!(d.commands.len(), 7);
+        let pos = d.follow_course().unwrap();
+        assert_eq!((pos.forward, pos.depth), (8, 3));
+        let pos = d.follow_course2().unwrap();
+        assert_eq!((pos.forward, pos.depth, pos.aim), (8, 24, 3));
+    }
+
+    #[test]
+    fn test_nested_repeat() {
+        let d = Day2::load_str("repeat 3 { repeat 4 { forward 1 } down 1 }\nrepeat 0 { up 9 }");
+        assert_eq!(d.commands.len(), 15);
+        let pos = d.follow_course().unwrap();
+        assert_eq!((pos.forward, pos.depth), (12, 3));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |script: &str| {
+            let e = Day2::parse(script).err().unwrap();
+            (e.line.unwrap(), e.msg)
+        };
+
+        assert_eq!(error("forward 1\nsideways 2\n"), (2, String::from("unknown command 'sideways'")));
+        assert_eq!(error("down\n"), (1, String::from("expected a number after 'down'")));
+        assert_eq!(error("up x\n"), (1, String::from("expected a number after 'up', found 'x'")));
+        assert_eq!(error("repeat 2 {\nforward 1\n"),
+                   (2, String::from("missing '}' for block opened on line 1")));
+        assert_eq!(error("forward 1\n}\n"), (2, String::from("unexpected '}'")));
+        assert_eq!(error("dive\nmacro dive { down 1 }\n"), (1, String::from("unknown command 'dive'")));
+        assert_eq!(error("macro up { down 1 }\n"), (1, String::from("bad macro name 'up'")));
+        assert_eq!(error("repeat 1000 { repeat 1001 { forward 1 } }").1,
+                   String::from("course expands to more than 1000000 commands"));
+        assert_eq!(Day2::parse("\n\nup 1 }").err().unwrap().to_string(), "line 3: unexpected '}'");
+
+        // Repeating an empty block is instant, however many times.
+        let length = |script: &str| Day2::parse(script).unwrap().commands.len();
+        assert_eq!(length("repeat 18446744073709551615 { }\nforward 1"), 1);
+        assert_eq!(length("macro nothing { }\nrepeat 18446744073709551615 { nothing }"), 0);
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("rs_aoc2021_day2_{}", std::process::id()));
+        fs::create_dir_all(dir.join("legs")).unwrap();
+        fs::write(dir.join("legs/dive.txt"), "macro dive { down 5 }\nforward 2\n").unwrap();
+        fs::write(dir.join("legs/loop.txt"), "#include \"loop.txt\"\n").unwrap();
+        fs::write(dir.join("legs/bad.txt"), "forward 1\nforward -1\n").unwrap();
+        fs::write(dir.join("course.txt"), "#include \"legs/dive.txt\"\ndive\nforward 4\n").unwrap();
+        fs::write(dir.join("cycle.txt"), "#include legs/loop.txt\n").unwrap();
+        fs::write(dir.join("broken.txt"), "up 1\n#include legs/bad.txt\n").unwrap();
+
+        let d = Day2::parse_file(&dir.join("course.txt")).unwrap();
+        let pos = d.follow_course2().unwrap();
+        assert_eq!((pos.forward, pos.depth, pos.aim), (6, 20, 5));
+
+        let e = Day2::parse_file(&dir.join("cycle.txt")).err().unwrap();
+        assert_eq!(e.file, Some(dir.join("legs/loop.txt")));
+        assert!(e.msg.ends_with("includes itself"));
+
+        let e = Day2::parse_file(&dir.join("broken.txt")).err().unwrap();
+        assert_eq!((e.file, e.line), (Some(dir.join("legs/bad.txt")), Some(2)));
+
+        let missing = dir.join("missing.txt");
+        let e = Day2::parse_file(&missing).err().unwrap();
+        assert_eq!(e.line, None);
+        assert!(e.to_string().starts_with(&format!("{}: can't read", missing.display())));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_surfacing() {
+        let d = Day2::load_str("down 2\nforward 3\nup 5\nforward 3\n");
+        let e = d.follow_course().err().unwrap();
+        assert_eq!((e.index, e.kind), (2, NavErrorKind::Surfaced));
+        assert_eq!(e.to_string(), "command 2 (up 5): course goes above the surface");
+        assert_eq!(d.part1(), Err("command 2 (up 5): course goes above the surface"));
+        assert_eq!(d.part2(), Err("command 3 (forward 3): course goes above the surface"));
+
+        // Negative aim is fine until forward brings the submarine up past the surface.
+        let e = d.follow_course2().err().unwrap();
+        assert_eq!((e.index, e.kind), (3, NavErrorKind::Surfaced));
+        assert_eq!(d.explain()[0].0, "part 1 error");
+    }
+
+    #[test]
+    fn test_overflow() {
+        let big = format!("down {}\nforward 2\n", i64::MAX);
+        let e = Day2::load_str(&big).follow_course2().err().unwrap();
+        assert_eq!((e.index, e.kind), (1, NavErrorKind::Overflow));
+
+        let huge = format!("forward {}\n", u64::MAX);
+        let e = Day2::load_str(&huge).follow_course().err().unwrap();
+        assert_eq!((e.index, e.kind), (0, NavErrorKind::Overflow));
+
+        let d = Day2::load_str(&format!("forward {}\ndown 3\n", i64::MAX));
+        assert_eq!(d.part1(), Err("answer overflows"));
+    }
+
+    #[test]
+    fn test_lateral() {
+        let d = Day2::load_str("down 2\nright 7\nforward 3\nleft 10\n");
+        let pos = d.navigate(&LateralModel).unwrap();
+        assert_eq!(pos, Position { forward: 3, depth: 6, aim: 2, lateral: -3 });
+
+        let e = d.navigate(&AimModel).err().unwrap();
+        assert_eq!((e.index, e.kind), (1, NavErrorKind::Unsupported));
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let d = Day2::load_str("forward 5\ndown 5\nforward 8\nup 3\ndown 8\nforward 2\n");
+        let t = d.trajectory(&AimModel).unwrap();
+        assert_eq!(t.positions.len(), 7);
+        assert_eq!(t.positions[3], Position { forward: 13, depth: 40, aim: 5, lateral: 0 });
+        assert_eq!(t.positions.last(), Some(&d.follow_course2().unwrap()));
+
+        let csv = t.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "step,command,forward,depth,aim,lateral");
+        assert_eq!(lines[1], "0,,0,0,0,0");
+        assert_eq!(lines[4], "3,forward 8,13,40,5,0");
+
+        let direct = d.trajectory(&DirectModel).unwrap();
+        assert_eq!(direct.to_csv().lines().nth(2), Some("1,forward 5,5,0,0,0"));
+
+        let e = Day2::load_str("up 1\n").trajectory(&DirectModel).err().unwrap();
+        assert_eq!(e.kind, NavErrorKind::Surfaced);
+    }
+
+    #[test]
+    fn test_svg() {
+        let d = Day2::load_str("down 2\nforward 10\n");
+        let svg = d.trajectory(&DirectModel).unwrap().to_svg();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        // Start at the surface, dive straight down, then run to the far edge of the plot.
+        assert!(svg.contains("points=\"40.0,40.0 40.0,360.0 760.0,360.0\""));
+        assert!(svg.contains(">forward 10</text>"));
+        assert!(svg.contains(">depth 2</text>"));
+    }
+
+    // Shortest course length by brute force: every command, turning both ways.
+    fn shortest(forward: usize, depth: usize, max_dist: usize, max_aim: usize) -> Option<usize> {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::new();
+        seen.insert((0, 0, 0));
+        queue.push_back(((0, 0, 0), 0));
+
+        while let Some(((f, d, aim), len)) = queue.pop_front() {
+            if (f, d) == (forward, depth) {
+                return Some(len);
+            }
+            let mut next = Vec::new();
+            for dist in 1..=max_dist {
+                next.push((f + dist, d + aim * dist, aim));
+                next.push((f, d, aim + dist));
+                if aim >= dist {
+                    next.push((f, d, aim - dist));
+                }
+            }
+            for state in next {
+                if state.0 <= forward && state.1 <= depth && state.2 <= max_aim && seen.insert(state) {
+                    queue.push_back((state, len + 1));
+                }
+            }
+        }
+
+        None
+    }
+
+    #[test]
+    fn test_plan() {
+        let plan = Day2::plan(15, 60, PlanLimits::default()).unwrap();
+        assert_eq!(plan, vec![Command { dir: Dir::DOWN, dist: 4 }, Command { dir: Dir::FORWARD, dist: 15 }]);
+        assert_eq!(Day2::plan(0, 0, PlanLimits::default()), Ok(vec![]));
+        assert_eq!(Day2::plan(0, 5, PlanLimits::default()), Err(PlanError::Unreachable));
+
+        // 7 isn't a multiple of 10, so cruise at the surface first.
+        assert_eq!(Day2::plan(10, 7, PlanLimits::default()).unwrap().len(), 3);
+
+        let limits = PlanLimits { max_dist: Some(10), max_aim: Some(2) };
+        let plan = Day2::plan(23, 40, limits).unwrap();
+        assert!(plan.iter().all(|cmd| cmd.dist <= 10));
+        let pos = Day2::from_commands(plan).follow_course2().unwrap();
+        assert_eq!((pos.forward, pos.depth), (23, 40));
+        assert!(pos.aim <= 2);
+
+        assert_eq!(Day2::plan(10, 21, PlanLimits { max_dist: None, max_aim: Some(2) }),
+                   Err(PlanError::Unreachable));
+    }
+
+    #[test]
+    fn test_plan_is_shortest() {
+        let limits = PlanLimits { max_dist: Some(3), max_aim: Some(4) };
+        for forward in 0..=7 {
+            for depth in 0..=20 {
+                let plan = Day2::plan(forward, depth, limits);
+                let expected = shortest(forward, depth, 3, 4).ok_or(PlanError::Unreachable);
+                assert_eq!(plan.as_ref().map(|p| p.len()).map_err(|e| *e), expected,
+                           "target {},{}", forward, depth);
+                if let Ok(plan) = plan {
+                    let pos = Day2::from_commands(plan).follow_course2().unwrap();
+                    assert_eq!((pos.forward as usize, pos.depth as usize), (forward, depth));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_gives_up() {
+        // Short commands and a depth just shy of an aim of 1000 all the way.
+        let limits = PlanLimits { max_dist: Some(5), max_aim: None };
+        assert_eq!(Day2::plan_within(1000, 999999, limits, 10_000), Err(PlanError::GaveUp));
+        assert_eq!(Day2::plan_within(1000, 1000000, limits, 10_000).map(|p| p.len()), Ok(400));
+    }
 }
@@ -1,184 +1,370 @@
 use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fmt;
+use std::fs;
+use std::thread;
+
+// One diagnostic report, any number of bits.  Bit 0 is the leftmost (most significant) one,
+// packed 64 to a word.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    width: usize,
+    words: Vec<u64>,
+}
+
+impl Report {
+    pub fn new(width: usize) -> Report {
+        Report { width, words: vec![0; width.div_ceil(64)] }
+    }
+
+    // Parses a string of 0s and 1s, None if there's anything else in it.
+    pub fn parse(s: &str) -> Option<Report> {
+        let mut report = Report::new(s.len());
+        for (i, c) in s.chars().enumerate() {
+            match c {
+                '0' => {}
+                '1' => report.set(i, true),
+                _ => return None,
+            }
+        }
+
+        Some(report)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn bit(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn set(&mut self, i: usize, val: bool) {
+        if val {
+            self.words[i / 64] |= 1 << (i % 64);
+        } else {
+            self.words[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    // The report as a number, None if it doesn't fit in 64 bits.
+    pub fn value(&self) -> Option<u64> {
+        let mut val: u64 = 0;
+        for i in 0..self.width {
+            if val.leading_zeros() == 0 {
+                return None;
+            }
+            val = (val << 1) | self.bit(i) as u64;
+        }
+
+        Some(val)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in 0..self.width {
+            write!(f, "{}", if self.bit(i) { '1' } else { '0' })?;
+        }
+        Ok(())
+    }
+}
+
+// Which bit value a rating keeps at each step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Keep {
+    MostCommon,
+    LeastCommon,
+}
+
+// The order a rating looks at bit positions in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+// How to pick a bit value for a column: the most or least common one, `tie` when there are
+// as many ones as zeros.  `order` only matters for ratings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Criterion {
+    pub keep: Keep,
+    pub tie: bool,
+    pub order: ScanOrder,
+}
+
+impl Criterion {
+    pub const GAMMA: Criterion =
+        Criterion { keep: Keep::MostCommon, tie: false, order: ScanOrder::MsbFirst };
+    pub const EPSILON: Criterion =
+        Criterion { keep: Keep::LeastCommon, tie: false, order: ScanOrder::MsbFirst };
+    pub const OXYGEN: Criterion =
+        Criterion { keep: Keep::MostCommon, tie: true, order: ScanOrder::MsbFirst };
+    pub const CO2: Criterion =
+        Criterion { keep: Keep::LeastCommon, tie: false, order: ScanOrder::MsbFirst };
+
+    fn pick(&self, ones: usize, zeros: usize) -> bool {
+        if ones == zeros {
+            return self.tie;
+        }
+        match self.keep {
+            Keep::MostCommon => ones > zeros,
+            Keep::LeastCommon => ones < zeros,
+        }
+    }
+
+    // Bit position looked at in the given step.
+    fn position(&self, step: usize, width: usize) -> usize {
+        match self.order {
+            ScanOrder::MsbFirst => step,
+            ScanOrder::LsbFirst => width - 1 - step,
+        }
+    }
+}
+
+// Counts ones per column for many reports at once, 64 columns to a word.  Each word of
+// columns has a stack of bit planes holding the counts in binary, plane k is bit k of all
+// 64 counts, so adding a report is a ripple-carry add of its word into the planes.
+struct ColumnCounter {
+    planes: Vec<Vec<u64>>,
+}
+
+impl ColumnCounter {
+    fn new(width: usize) -> ColumnCounter {
+        ColumnCounter { planes: vec![Vec::new(); width.div_ceil(64)] }
+    }
+
+    fn add(&mut self, report: &Report) {
+        for (planes, word) in self.planes.iter_mut().zip(&report.words) {
+            let mut carry = *word;
+            let mut k = 0;
+            while carry != 0 {
+                if k == planes.len() {
+                    planes.push(0);
+                }
+                let next = planes[k] & carry;
+                planes[k] ^= carry;
+                carry = next;
+                k += 1;
+            }
+        }
+    }
+
+    fn counts(&self, width: usize) -> Vec<usize> {
+        (0..width)
+            .map(|position| {
+                let planes = &self.planes[position / 64];
+                planes.iter().enumerate()
+                    .map(|(k, plane)| (((plane >> (position % 64)) & 1) as usize) << k)
+                    .sum()
+            })
+            .collect()
+    }
+}
 
 pub struct Day3 {
     report_len: usize,
-    reports: Vec<u64>,
+    reports: Vec<Report>,
+    // Worker threads for counting columns.
+    threads: usize,
 }
 
 impl Day3 {
     pub fn load(filename: &str) -> Day3 {
-        let mut report_len = 0;
-        let mut reports: Vec<u64> = Vec::new();
+        Day3::load_str(&fs::read_to_string(filename).unwrap())
+    }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+    pub fn load_str(input: &str) -> Day3 {
+        let mut report_len = 0;
+        let mut reports: Vec<Report> = Vec::new();
 
-        for line in reader.lines() {
-            let l = line.unwrap();
+        for (n, l) in input.lines().enumerate() {
             let report = l.trim();
 
-            if report.len() > 0 {
+            if !report.is_empty() {
                 // set report_len on first viable report seen
                 if report_len == 0 {
                     report_len = report.len();
                 }
+                if report.len() != report_len {
+                    panic!("line {}: report has {} bits, expected {}", n + 1, report.len(), report_len);
+                }
 
-                // convert string to a value and store it.
-                let mut report_val: u64 = 0;
-                for c in report.chars() {
-                    report_val <<= 1;
-                    if c == '1' {
-                        report_val |= 1;
-                    }
+                match Report::parse(report) {
+                    Some(report) => reports.push(report),
+                    None => panic!("line {}: not a binary number: {}", n + 1, report),
                 }
-                reports.push(report_val);
             }
         }
 
         Day3 {
             report_len,
-            reports: reports,
+            reports,
+            threads: 1,
         }
     }
 
-    fn gamma(&self) -> u64 {
-        let mut balance: Vec<isize> = vec![0; self.report_len];
-
-        for report in &self.reports {
-            for position in 0..self.report_len {
-                let mask = 1 << position;
-                if (report & mask) > 0 {
-                    balance[position] += 1;
-                } else {
-                    balance[position] -= 1;
-                }
-            }
-        }
+    // Reports that are already parsed.  They must all be the same width.
+    pub fn from_reports(reports: Vec<Report>) -> Day3 {
+        let report_len = reports.first().map_or(0, |r| r.width());
+        assert!(reports.iter().all(|r| r.width() == report_len), "reports differ in width");
 
-        let mut result = 0;
-        for position in 0..self.report_len {
-            if balance[position] > 0 {
-                result |= 1 << position;
-            }
-        }
-
-        result
+        Day3 { report_len, reports, threads: 1 }
     }
 
-    fn epsilon(&self) -> u64 {
-        let mut balance: Vec<isize> = vec![0; self.report_len];
+    // Counts columns on this many threads, for dumps with millions of reports.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
 
-        for report in &self.reports {
-            for position in 0..self.report_len {
-                let mask = 1 << position;
-                if (report & mask) > 0 {
-                    balance[position] += 1;
-                } else {
-                    balance[position] -= 1;
-                }
-            }
+    // Ones in each bit position, split between threads when there are enough reports.
+    pub fn ones(&self) -> Vec<usize> {
+        let chunk = self.reports.len().div_ceil(self.threads).max(1 << 16);
+        if chunk >= self.reports.len() {
+            return self.count_ones(&self.reports);
         }
 
-        let mut result = 0;
-        for position in 0..self.report_len {
-            if balance[position] < 0 {
-                result |= 1 << position;
+        let parts: Vec<Vec<usize>> = thread::scope(|scope| {
+            let workers: Vec<_> = self.reports.chunks(chunk)
+                .map(|reports| scope.spawn(move || self.count_ones(reports)))
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        let mut ones = vec![0; self.report_len];
+        for part in parts {
+            for (total, count) in ones.iter_mut().zip(part) {
+                *total += count;
             }
         }
 
-        result
+        ones
     }
 
-    fn oxy_recursive(&self, reports: &Vec<u64>, bit_pos: usize) -> u64 {
-        // find most common value for the given bit position
-        let mask: u64 = 1 << bit_pos;
-        let mut balance: i64 = 0;
+    fn count_ones(&self, reports: &[Report]) -> Vec<usize> {
+        let mut counter = ColumnCounter::new(self.report_len);
         for report in reports {
-            if (report & mask) == mask {
-                balance += 1;
-            } else {
-                balance -= 1;
-            }
+            counter.add(report);
         }
-        let keep = if balance >= 0 { 1 << bit_pos } else { 0 };
 
-        // keep only values that meet the keep criteria
-        let mut filtered: Vec<u64> = Vec::new();
-        for report in reports {
-            if (report & mask) == keep {
-                filtered.push(*report);
-            }
-        }
+        counter.counts(self.report_len)
+    }
 
-        // If only one report left, use it, otherwise recurse.
-        let retval;
-        if filtered.len() == 1 {
-            retval = filtered[0]
-        } else {
-            retval = self.oxy_recursive(&filtered, bit_pos - 1)
+    // The bit each column picks under criterion, over all the reports.
+    pub fn common(&self, criterion: Criterion) -> Report {
+        let mut result = Report::new(self.report_len);
+        for (position, ones) in self.ones().iter().enumerate() {
+            result.set(position, criterion.pick(*ones, self.reports.len() - ones));
         }
 
-        retval
+        result
+    }
+
+    fn gamma(&self) -> Report {
+        self.common(Criterion::GAMMA)
     }
 
-    fn oxygen(&self) -> u64 {
-        self.oxy_recursive(&self.reports, self.report_len - 1)
+    fn epsilon(&self) -> Report {
+        self.common(Criterion::EPSILON)
     }
 
-    fn co2_recursive(&self, reports: &Vec<u64>, bit_pos: usize) -> u64 {
-        // find most common value for the given bit position
-        let mask: u64 = 1 << bit_pos;
-        let mut balance: i64 = 0;
-        for report in reports {
-            if (report & mask) == mask {
-                balance += 1;
-            } else {
-                balance -= 1;
-            }
-        }
-        let keep = if balance < 0 { 1 << bit_pos } else { 0 };
+    // Rates the reports under each criterion, in one sweep over the bit positions.
+    //
+    // Each rating narrows its own set of reports one bit position at a time, keeping those
+    // whose bit is the one its criterion picks among the reports it has left.  A position
+    // where every report left agrees can't tell them apart, so it keeps them all.
+    pub fn ratings(&self, criteria: &[Criterion]) -> Vec<Result<Report, &'static str>> {
+        let mut left: Vec<Vec<&Report>> = vec![self.reports.iter().collect(); criteria.len()];
+
+        for step in 0..self.report_len {
+            for (criterion, left) in criteria.iter().zip(left.iter_mut()) {
+                if left.len() <= 1 {
+                    continue;
+                }
+                let position = criterion.position(step, self.report_len);
+                let ones = left.iter().filter(|r| r.bit(position)).count();
+                let zeros = left.len() - ones;
+                if ones == 0 || zeros == 0 {
+                    continue;
+                }
 
-        // keep only values that meet the keep criteria
-        let mut filtered: Vec<u64> = Vec::new();
-        for report in reports {
-            if (report & mask) == keep {
-                filtered.push(*report);
+                let want = criterion.pick(ones, zeros);
+                left.retain(|r| r.bit(position) == want);
             }
         }
 
-        // If only one report left, use it, otherwise recurse.
-        let retval;
-        if filtered.len() == 1 {
-            retval = filtered[0]
-        } else {
-            retval = self.co2_recursive(&filtered, bit_pos - 1)
-        }
+        left.iter()
+            .map(|left| match left.len() {
+                0 => Err("no reports"),
+                1 => Ok(left[0].clone()),
+                _ => Err("rating is not unique"),
+            })
+            .collect()
+    }
 
-        retval
+    pub fn rating(&self, criterion: Criterion) -> Result<Report, &'static str> {
+        self.ratings(&[criterion]).remove(0)
     }
 
-    fn co2(&self) -> u64 {
-        self.co2_recursive(&self.reports, self.report_len - 1)
+    fn oxygen(&self) -> Result<Report, &'static str> {
+        self.rating(Criterion::OXYGEN)
+    }
+
+    fn co2(&self) -> Result<Report, &'static str> {
+        self.rating(Criterion::CO2)
+    }
+}
+
+// Product of two ratings, which have to fit in 64 bits to be numbers at all.
+fn product(a: &Report, b: &Report) -> Result<usize, &'static str> {
+    match (a.value(), b.value()) {
+        (Some(a), Some(b)) => a.checked_mul(b).map(|v| v as usize).ok_or("answer overflows"),
+        _ => Err("ratings are wider than 64 bits"),
     }
 }
 
 impl Day for Day3 {
     fn part1(&self) -> Result<usize, &str> {
-        Ok((self.gamma() * self.epsilon()) as usize)
+        product(&self.gamma(), &self.epsilon())
     }
 
     fn part2(&self) -> Result<usize, &str> {
-        Ok((self.oxygen() * self.co2()) as usize)
+        let mut ratings = self.ratings(&[Criterion::OXYGEN, Criterion::CO2]).into_iter();
+        let oxygen = ratings.next().unwrap()?;
+        let co2 = ratings.next().unwrap()?;
+
+        product(&oxygen, &co2)
+    }
+
+    fn explain(&self) -> Vec<(String, String)> {
+        // Show each rating in decimal and as the report bits it came from.
+        let show = |rating: Result<Report, &str>| match rating {
+            Ok(r) => match r.value() {
+                Some(val) => format!("{} ({})", val, r),
+                None => r.to_string(),
+            },
+            Err(msg) => msg.to_string(),
+        };
+
+        vec![
+            (String::from("gamma"), show(Ok(self.gamma()))),
+            (String::from("epsilon"), show(Ok(self.epsilon()))),
+            (String::from("oxygen"), show(self.oxygen())),
+            (String::from("co2"), show(self.co2())),
+        ]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rng::Rng;
     //use crate::day::Day;
 
+    // Ones in each bit position, one report and one bit at a time, to check ones() against.
+    fn ones_by_bit(d: &Day3) -> Vec<usize> {
+        (0..d.report_len).map(|i| d.reports.iter().filter(|r| r.bit(i)).count()).collect()
+    }
+
     #[test]
     fn test_load() {
         let d = Day3::load("data/day3_example1.txt");
@@ -188,25 +374,33 @@ mod tests {
     #[test]
     fn test_gamma() {
         let d = Day3::load("data/day3_example1.txt");
-        assert_eq!(d.gamma(), 22);
+        assert_eq!(d.gamma().value(), Some(22));
     }
 
     #[test]
     fn test_epsilon() {
         let d = Day3::load("data/day3_example1.txt");
-        assert_eq!(d.epsilon(), 9);
+        assert_eq!(d.epsilon().value(), Some(9));
     }
 
     #[test]
     fn test_oxygen() {
         let d = Day3::load("data/day3_example1.txt");
-        assert_eq!(d.oxygen(), 23);
+        assert_eq!(d.oxygen().unwrap().value(), Some(23));
     }
 
     #[test]
     fn test_co2() {
         let d = Day3::load("data/day3_example1.txt");
-        assert_eq!(d.co2(), 10);
+        assert_eq!(d.co2().unwrap().value(), Some(10));
+    }
+
+    #[test]
+    fn test_explain() {
+        let d = Day3::load("data/day3_example1.txt");
+        let details = d.explain();
+        assert_eq!(details[0], (String::from("gamma"), String::from("22 (10110)")));
+        assert_eq!(details[3], (String::from("co2"), String::from("10 (01010)")));
     }
 
     #[test]
@@ -222,4 +416,80 @@ mod tests {
         let d = Day3::load("data/day3_example1.txt");
         assert_eq!(d.part2(), Ok(230));
     }
+
+    #[test]
+    fn test_wide_reports() {
+        // 70 bits, the first 64 the same in every report.
+        let prefix = "1".repeat(64);
+        let input = format!("{p}000111\n{p}100110\n{p}110101\n", p = prefix);
+        let d = Day3::load_str(&input);
+        assert_eq!(d.gamma().to_string(), format!("{}100111", prefix));
+        assert_eq!(d.epsilon().to_string(), format!("{}011000", "0".repeat(64)));
+        assert_eq!(d.oxygen().unwrap().to_string(), format!("{}110101", prefix));
+        assert_eq!(d.co2().unwrap().to_string(), format!("{}000111", prefix));
+        assert_eq!(d.part1(), Err("ratings are wider than 64 bits"));
+        assert_eq!(d.explain()[0].1, format!("{}100111", prefix));
+        assert_eq!(d.explain()[1].1, format!("24 ({}011000)", "0".repeat(64)));
+    }
+
+    #[test]
+    fn test_not_unique() {
+        let d = Day3::load_str("101\n101\n010\n");
+        assert_eq!(d.oxygen(), Err("rating is not unique"));
+        assert_eq!(d.co2().unwrap().value(), Some(2));
+        assert_eq!(d.part2(), Err("rating is not unique"));
+        assert_eq!(Day3::load_str("").part2(), Err("no reports"));
+    }
+
+    #[test]
+    #[should_panic(expected = "line 2: report has 4 bits, expected 3")]
+    fn test_ragged() {
+        Day3::load_str("101\n1011\n");
+    }
+
+    #[test]
+    fn test_ratings() {
+        let d = Day3::load("data/day3_example1.txt");
+        let lsb = |keep, tie| Criterion { keep, tie, order: ScanOrder::LsbFirst };
+        let criteria = [
+            Criterion::OXYGEN,
+            Criterion::CO2,
+            lsb(Keep::MostCommon, true),
+            lsb(Keep::LeastCommon, false),
+            lsb(Keep::MostCommon, false),
+        ];
+
+        let values: Vec<Option<u64>> = d.ratings(&criteria).iter()
+            .map(|r| r.as_ref().unwrap().value())
+            .collect();
+        assert_eq!(values, vec![Some(23), Some(10), Some(30), Some(25), Some(2)]);
+        assert_eq!(d.rating(criteria[3]).unwrap().value(), Some(25));
+        assert_eq!(d.ratings(&[]), vec![]);
+
+        let ones_on_tie = Criterion { tie: true, ..Criterion::GAMMA };
+        assert_eq!(Day3::load_str("10\n01\n").common(ones_on_tie).to_string(), "11");
+    }
+
+    #[test]
+    fn test_column_counter() {
+        let d = Day3::load("data/day3_example1.txt");
+        assert_eq!(d.ones(), vec![7, 5, 8, 7, 5]);
+        assert_eq!(d.ones(), ones_by_bit(&d));
+
+        // Wide reports, counts up past a few planes, spread over threads.
+        let mut reports = Vec::new();
+        let mut rng = Rng::new(12345);
+        for _ in 0..100_000 {
+            let mut report = Report::new(130);
+            for i in 0..130 {
+                report.set(i, rng.below(3) == 0);
+            }
+            reports.push(report);
+        }
+        let mut d = Day3::from_reports(reports);
+        let expected = ones_by_bit(&d);
+        assert_eq!(d.ones(), expected);
+        d.set_threads(3);
+        assert_eq!(d.ones(), expected);
+    }
 }
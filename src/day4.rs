@@ -1,94 +1,187 @@
 use crate::day::Day;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use crate::rng::Rng;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
 
-// use lazy_static::lazy_static;
-use regex::Regex;
+// Ways a card can win.  A card wins as soon as any one of its rules' patterns is complete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WinPattern {
+    Rows,
+    Columns,
+    // Both diagonals, square cards only.
+    Diagonals,
+    // The four corner squares.
+    Corners,
+    // Every square.
+    Blackout,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WinRules {
+    pub patterns: Vec<WinPattern>,
+    // Centre square starts marked and doesn't count toward the score.  Needs an odd number
+    // of rows and columns.
+    pub free_centre: bool,
+}
 
-struct BingoCard {
+impl Default for WinRules {
+    // The puzzle's rules: any full row or column.
+    fn default() -> WinRules {
+        WinRules { patterns: vec![WinPattern::Rows, WinPattern::Columns], free_centre: false }
+    }
+}
+
+pub struct BingoCard {
+    rows: usize,
+    cols: usize,
     // maps numbers on the card to their coordinates.
     values: HashMap<usize, (usize, usize)>,
+    // Numbers by square, row by row.
+    squares: Vec<usize>,
+    free: Option<usize>,
+    // Squares in each winning line, and the lines through each square.
+    lines: Vec<Vec<usize>>,
+    lines_through: Vec<Vec<usize>>,
 }
 
 impl BingoCard {
-    fn from_vecs(vecs: Vec<Vec<usize>>) -> BingoCard {
-        let mut values = HashMap::new();
+    // Builds a card from its rows, checking they make a card the rules can be played on.
+    pub fn new(rows: Vec<Vec<usize>>, rules: &WinRules) -> Result<BingoCard, String> {
+        let (height, width) = (rows.len(), rows.first().map_or(0, |r| r.len()));
+        if width == 0 {
+            return Err(String::from("empty card"));
+        }
+        if let Some(row) = rows.iter().position(|r| r.len() != width) {
+            return Err(format!("row {} has {} numbers, expected {}",
+                               row + 1, rows[row].len(), width));
+        }
+        if rules.patterns.contains(&WinPattern::Diagonals) && height != width {
+            return Err(format!("diagonals need a square card, not {}x{}", height, width));
+        }
+        if rules.free_centre && (height % 2 == 0 || width % 2 == 0 || height * width == 1) {
+            return Err(format!("a {}x{} card has no centre square", height, width));
+        }
 
-        for (row_no, row) in vecs.iter().enumerate() {
+        let mut values = HashMap::new();
+        for (row_no, row) in rows.iter().enumerate() {
             for (col_no, value) in row.iter().enumerate() {
-                values.insert(*value, (col_no, row_no));
+                if values.insert(*value, (col_no, row_no)).is_some() {
+                    return Err(format!("{} is on the card twice", value));
+                }
             }
         }
 
-        BingoCard { values }
-    }
-
-    // check to see if this match array contains all true in one row or column.
-    fn is_win(matches: [[bool; 5]; 5]) -> bool {
-        // check rows (all Y equal)
-        for y in 0..5 {
-            let mut all_match = true;
-            for x in 0..5 {
-                if !matches[x][y] {
-                    all_match = false;
-                    break;
+        let at = |x: usize, y: usize| y * width + x;
+        let mut lines: Vec<Vec<usize>> = Vec::new();
+        for pattern in &rules.patterns {
+            match pattern {
+                WinPattern::Rows => {
+                    lines.extend((0..height).map(|y| (0..width).map(|x| at(x, y)).collect()));
                 }
-            }
-            if all_match {
-                return true;
+                WinPattern::Columns => {
+                    lines.extend((0..width).map(|x| (0..height).map(|y| at(x, y)).collect()));
+                }
+                WinPattern::Diagonals => {
+                    lines.push((0..width).map(|i| at(i, i)).collect());
+                    lines.push((0..width).map(|i| at(width - 1 - i, i)).collect());
+                }
+                WinPattern::Corners => {
+                    let (right, bottom) = (width - 1, height - 1);
+                    let mut corners =
+                        vec![at(0, 0), at(right, 0), at(0, bottom), at(right, bottom)];
+                    corners.sort_unstable();
+                    corners.dedup();
+                    lines.push(corners);
+                }
+                WinPattern::Blackout => lines.push((0..width * height).collect()),
             }
         }
 
-        // check columns (all X equal)
-        for x in 0..5 {
-            let mut all_match = true;
-            for y in 0..5 {
-                if !matches[x][y] {
-                    all_match = false;
-                    break;
-                }
-            }
-            if all_match {
-                return true;
+        let mut lines_through = vec![Vec::new(); width * height];
+        for (n, line) in lines.iter().enumerate() {
+            for square in line {
+                lines_through[*square].push(n);
             }
         }
 
-        false
+        Ok(BingoCard {
+            rows: height,
+            cols: width,
+            values,
+            squares: rows.concat(),
+            free: if rules.free_centre { Some(at(width / 2, height / 2)) } else { None },
+            lines,
+            lines_through,
+        })
     }
 
-    fn card_score(&self, matches: [[bool; 5]; 5]) -> usize {
-        let mut score = 0;
-        for x in 0..5 {
-            for y in 0..5 {
-                if !matches[x][y] {
-                    for (k, v) in self.values.iter() {
-                        if v == &(x, y) {
-                            score += k;
-                        }
-                    }
-                }
-            }
-        }
+    // (rows, columns)
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    // Numbers on the card, row by row.
+    pub fn numbers(&self) -> &[usize] {
+        &self.squares
+    }
 
-        score
+    // Coordinates (column, row) of value on the card.
+    pub fn position(&self, value: usize) -> Option<(usize, usize)> {
+        self.values.get(&value).copied()
+    }
+
+    // Like play(), but only the round, with `when` giving the round each square (numbered
+    // across the rows) is called in, usize::MAX for never.  No allocation, for simulating many
+    // games.  A line is complete when its last square is called, so that's the earliest of
+    // those.
+    pub fn winning_round_when<F: Fn(usize) -> usize>(&self, when: F) -> Option<usize> {
+        let when = |square: usize| match self.free {
+            Some(free) if free == square => 0,
+            _ => when(square),
+        };
+        let round = self.lines.iter()
+            .map(|line| line.iter().map(|square| when(*square)).max().unwrap_or(0))
+            .min()?;
+
+        if round == usize::MAX { None } else { Some(round) }
     }
 
     // run a sequence of calls with this card, it returns None if no win, Some((round, score))
     // if it eventually wins.
-    pub fn play(&self, calls: &Vec<usize>) -> Option<(usize, usize)> {
-        let mut matched: [[bool; 5]; 5] = [[false; 5]; 5];
-        let mut round: usize = 0;
-        for call in calls {
-            round += 1;
-            if self.values.contains_key(&call) {
-                let (x, y) = self.values.get(&call).unwrap();
-                matched[*x][*y] = true;
+    pub fn play(&self, calls: &[usize]) -> Option<(usize, usize)> {
+        // Squares marked so far in each line, and the sum of the unmarked numbers.
+        let mut marked_in: Vec<usize> = vec![0; self.lines.len()];
+        let mut marked: Vec<bool> = vec![false; self.squares.len()];
+        let mut unmarked: usize = self.squares.iter().sum();
 
-                if BingoCard::is_win(matched) {
-                    let score = self.card_score(matched) * call;
-                    return Some((round, score));
-                }
+        if let Some(free) = self.free {
+            marked[free] = true;
+            unmarked -= self.squares[free];
+            for line in &self.lines_through[free] {
+                marked_in[*line] += 1;
+            }
+        }
+
+        for (round, call) in calls.iter().enumerate() {
+            let square = match self.values.get(call) {
+                Some((x, y)) => y * self.cols + x,
+                None => continue,
+            };
+            if marked[square] {
+                continue;
+            }
+            marked[square] = true;
+            unmarked -= call;
+
+            let mut won = false;
+            for line in &self.lines_through[square] {
+                marked_in[*line] += 1;
+                won |= marked_in[*line] == self.lines[*line].len();
+            }
+            if won {
+                return Some((round + 1, unmarked * call));
             }
         }
 
@@ -97,67 +190,520 @@ impl BingoCard {
     }
 }
 
+// A set of numbers from the cards, as bits indexed by where the number comes in a list of
+// every number on them.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct NumberSet(Vec<u64>);
+
+impl NumberSet {
+    fn new(size: usize) -> NumberSet {
+        NumberSet(vec![0; size.div_ceil(64)])
+    }
+
+    fn insert(&mut self, n: usize) {
+        self.0[n / 64] |= 1 << (n % 64);
+    }
+
+    fn remove(&mut self, n: usize) {
+        self.0[n / 64] &= !(1 << (n % 64));
+    }
+
+    fn contains(&self, n: usize) -> bool {
+        self.0[n / 64] & (1 << (n % 64)) != 0
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    fn is_subset(&self, other: &NumberSet) -> bool {
+        self.0.iter().zip(&other.0).all(|(a, b)| a & !b == 0)
+    }
+
+    fn union(&self, other: &NumberSet) -> NumberSet {
+        NumberSet(self.0.iter().zip(&other.0).map(|(a, b)| a | b).collect())
+    }
+
+    // How many of these aren't in other.
+    fn count_outside(&self, other: &NumberSet) -> usize {
+        self.0.iter().zip(&other.0).map(|(a, b)| (a & !b).count_ones() as usize).sum()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.0.len() * 64).filter(move |n| self.contains(*n))
+    }
+}
+
+// Which end of the game a card should finish at in a rigged call order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finish {
+    // Wins before any other card does.
+    First,
+    // Wins after every other card has.
+    Last,
+}
+
+// Search steps rig() takes before settling for the best order it has found.  Making a card
+// win last is a covering problem, and a full-size game is far too big to search exhaustively.
+const RIG_SEARCH_LIMIT: usize = 200_000;
+
+// What rig() came up with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rigged {
+    // The shortest call order there is.
+    Shortest(Vec<usize>),
+    // No call order works.
+    Impossible,
+    // The search hit its limit: the shortest order found by then, if any.
+    Unfinished(Option<Vec<usize>>),
+}
+
+// Search state for rigging a card to win last.  The calls are every number in `before`, in
+// any order, then `last`, which completes one of the target's lines.  Every other card needs
+// a line inside `before` and the target mustn't have one.
+struct LastSearch<'a> {
+    target: &'a [NumberSet],
+    others: Vec<&'a [NumberSet]>,
+    last: usize,
+    // Sets already searched from.  The best only gets shorter, so there's nothing new there.
+    seen: HashSet<NumberSet>,
+    best: Option<(NumberSet, usize)>,
+    steps: usize,
+    limit: usize,
+    gave_up: bool,
+}
+
+impl LastSearch<'_> {
+    fn search(&mut self, before: NumberSet) {
+        let called = before.len() + 1;
+        let beaten = |best: &Option<(NumberSet, usize)>, calls: usize| {
+            best.as_ref().is_some_and(|(b, _)| calls > b.len())
+        };
+        if beaten(&self.best, called) || !self.seen.insert(before.clone()) {
+            return;
+        }
+        if self.steps == self.limit {
+            self.gave_up = true;
+            return;
+        }
+        self.steps += 1;
+
+        // Branch on the card with the fewest lines left to win by, and bound by the card
+        // that needs the most new calls whichever line it wins by.
+        let mut bound = called;
+        let mut pick: Option<Vec<(usize, &NumberSet)>> = None;
+        for card in &self.others {
+            if card.iter().any(|line| line.is_subset(&before)) {
+                continue;
+            }
+            let mut options: Vec<(usize, &NumberSet)> = card.iter()
+                .filter(|line| !line.contains(self.last))
+                .map(|line| (line.count_outside(&before), line))
+                .collect();
+            let cheapest = match options.iter().map(|(cost, _)| *cost).min() {
+                Some(cost) => cost,
+                None => return,
+            };
+            bound = bound.max(called + cheapest);
+            if pick.as_ref().is_none_or(|p| options.len() < p.len()) {
+                options.sort_by_key(|(cost, _)| *cost);
+                pick = Some(options);
+            }
+        }
+        if beaten(&self.best, bound) {
+            return;
+        }
+
+        let options = match pick {
+            Some(options) => options,
+            None => {
+                self.best = Some((before, self.last));
+                return;
+            }
+        };
+        let nexts: Vec<NumberSet> = options.iter().map(|(_, line)| before.union(line)).collect();
+        for next in nexts {
+            if !self.target.iter().any(|line| line.is_subset(&next)) {
+                self.search(next);
+            }
+        }
+    }
+}
+
+// How cards fare over many random orderings of the calls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Odds {
+    pub trials: usize,
+    // Chance of each card winning first and winning last.  Cards winning on the same call
+    // share the win.
+    pub first: Vec<f64>,
+    pub last: Vec<f64>,
+    // rounds[card][r] is the number of trials the card won in round r + 1.
+    pub rounds: Vec<Vec<usize>>,
+}
+
+impl Odds {
+    // Average winning round of a card over the trials it won in, None if it never did.
+    pub fn mean_round(&self, card: usize) -> Option<f64> {
+        let wins: usize = self.rounds[card].iter().sum();
+        let total: usize = self.rounds[card].iter().enumerate().map(|(r, n)| (r + 1) * n).sum();
+
+        if wins == 0 { None } else { Some(total as f64 / wins as f64) }
+    }
+}
+
+impl fmt::Display for Odds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} trials", self.trials)?;
+        writeln!(f, "card   first    last  mean round")?;
+        for card in 0..self.first.len() {
+            let mean = match self.mean_round(card) {
+                Some(mean) => format!("{:.1}", mean),
+                None => String::from("-"),
+            };
+            writeln!(f, "{:>4}  {:>5.1}%  {:>5.1}%  {:>10}",
+                     card + 1, 100.0 * self.first[card], 100.0 * self.last[card], mean)?;
+        }
+        Ok(())
+    }
+}
+
+// A number marked on a card, at (col, row).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Mark {
+    pub card: usize,
+    pub col: usize,
+    pub row: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Win {
+    pub card: usize,
+    pub score: usize,
+}
+
+// What happened on one call.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Turn {
+    pub round: usize,
+    pub call: usize,
+    pub marks: Vec<Mark>,
+    pub wins: Vec<Win>,
+}
+
+// A card's place in the final ranking.  Cards winning on the same call share a place, cards
+// that never win have no place, round or score and come last.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Placing {
+    pub place: Option<usize>,
+    pub card: usize,
+    pub round: Option<usize>,
+    pub score: Option<usize>,
+}
+
+// A whole game, call by call.  Cards are numbered from 0, and drop out once they've won.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Timeline {
+    pub turns: Vec<Turn>,
+    pub ranking: Vec<Placing>,
+}
+
+impl Timeline {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for turn in &self.turns {
+            write!(f, "round {}: {} marked on {} card(s)",
+                   turn.round, turn.call, turn.marks.len())?;
+            for win in &turn.wins {
+                write!(f, ", card {} wins ({})", win.card + 1, win.score)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "ranking:")?;
+        for placing in &self.ranking {
+            match (placing.place, placing.round, placing.score) {
+                (Some(place), Some(round), Some(score)) => {
+                    writeln!(f, "{:>4}. card {}, round {}, score {}",
+                             place, placing.card + 1, round, score)?
+                }
+                _ => writeln!(f, "   -  card {}, no win", placing.card + 1)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct Day4 {
-    calls: Vec<usize>,
-    cards: Vec<BingoCard>,
+    pub(crate) calls: Vec<usize>,
+    pub(crate) cards: Vec<BingoCard>,
 }
 
 impl Day4 {
     pub fn load(filename: &str) -> Day4 {
-        // println!("Loading.");
-        lazy_static! {
-            static ref ROW_RE: Regex =
-                Regex::new("([0-9]+) +([0-9]+) +([0-9]+) +([0-9]+) +([0-9]+)").unwrap();
-        }
-        let mut on_calls = true;
-        let mut card_line = 0;
+        Day4::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day4 {
+        Day4::parse(input, &WinRules::default()).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Parses the calls and cards, played by the given rules.  Cards are blocks of rows
+    // separated by blank lines, and must all be the same shape.
+    pub fn parse(input: &str, rules: &WinRules) -> Result<Day4, String> {
+        let mut lines = input.lines().enumerate();
         let mut calls = Vec::new();
-        let mut card: Vec<Vec<usize>> = Vec::new();
+        if let Some((_, l)) = lines.next() {
+            for call_str in l.split(',') {
+                let call = call_str.trim().parse::<usize>()
+                    .map_err(|_| format!("line 1: bad call '{}'", call_str.trim()))?;
+                calls.push(call);
+            }
+        }
+
+        // (first line, rows) of each card
+        let mut blocks: Vec<(usize, Vec<Vec<usize>>)> = Vec::new();
+        let mut in_card = false;
+        for (n, l) in lines {
+            if l.trim().is_empty() {
+                in_card = false;
+                continue;
+            }
+            let mut row = Vec::new();
+            for word in l.split_whitespace() {
+                let val = word.parse::<usize>()
+                    .map_err(|_| format!("line {}: bad number '{}'", n + 1, word))?;
+                row.push(val);
+            }
+            if !in_card {
+                blocks.push((n + 1, Vec::new()));
+                in_card = true;
+            }
+            blocks.last_mut().unwrap().1.push(row);
+        }
+
         let mut cards: Vec<BingoCard> = Vec::new();
+        for (line, rows) in blocks {
+            let card = BingoCard::new(rows, rules)
+                .map_err(|e| format!("card at line {}: {}", line, e))?;
+            if let Some(first) = cards.first() {
+                if card.shape() != first.shape() {
+                    return Err(format!("card at line {} is {}x{}, the first card is {}x{}",
+                                       line, card.rows, card.cols, first.rows, first.cols));
+                }
+            }
+            cards.push(card);
+        }
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = line.unwrap();
-
-            if on_calls {
-                for call_str in l.split(",") {
-                    calls.push(call_str.parse::<usize>().unwrap());
-                }
-                on_calls = false;
-                card_line = 0;
-            } else {
-                // store values
-                // Do RE Magic.
-                let caps = ROW_RE.captures(&l);
-                match caps {
-                    Some(caps) => {
-                        let mut row: Vec<usize> = Vec::new();
-                        row.push(caps[1].parse::<usize>().unwrap());
-                        row.push(caps[2].parse::<usize>().unwrap());
-                        row.push(caps[3].parse::<usize>().unwrap());
-                        row.push(caps[4].parse::<usize>().unwrap());
-                        row.push(caps[5].parse::<usize>().unwrap());
-                        card.push(row);
-                        card_line += 1;
-                        // println!("card_line = {}", card_line);
-                        if card_line == 5 {
-                            // panic!();
-                            cards.push(BingoCard::from_vecs(card));
-                            card_line = 0;
-                            card = Vec::new();
-                        }
+        Ok(Day4 { calls, cards })
+    }
+
+    pub fn calls(&self) -> &[usize] {
+        &self.calls
+    }
+
+    pub fn cards(&self) -> &[BingoCard] {
+        &self.cards
+    }
+
+    // Plays the game through, recording every call.
+    pub fn timeline(&self) -> Timeline {
+        let results: Vec<Option<(usize, usize)>> =
+            self.cards.iter().map(|c| c.play(&self.calls)).collect();
+        let mut marked: Vec<Vec<bool>> =
+            self.cards.iter().map(|c| vec![false; c.squares.len()]).collect();
+
+        let mut turns = Vec::new();
+        for (n, call) in self.calls.iter().enumerate() {
+            let round = n + 1;
+            let mut turn = Turn { round, call: *call, marks: Vec::new(), wins: Vec::new() };
+
+            for (card_no, card) in self.cards.iter().enumerate() {
+                // Cards that have already won are out of the game.
+                let result = results[card_no];
+                if result.is_some_and(|(won, _)| won < round) {
+                    continue;
+                }
+                if let Some((col, row)) = card.position(*call) {
+                    let square = row * card.cols + col;
+                    if Some(square) != card.free && !marked[card_no][square] {
+                        marked[card_no][square] = true;
+                        turn.marks.push(Mark { card: card_no, col, row });
+                    }
+                }
+                if let Some((won, score)) = result {
+                    if won == round {
+                        turn.wins.push(Win { card: card_no, score });
                     }
-                    None => {
-                        // println!("No match.");
-                        // Blank line
+                }
+            }
+            turns.push(turn);
+        }
+
+        // Competition ranking: earliest win first, ties share a place.
+        let mut order: Vec<usize> = (0..self.cards.len()).collect();
+        order.sort_by_key(|card| (results[*card].map_or(usize::MAX, |(round, _)| round), *card));
+        let mut ranking: Vec<Placing> = Vec::new();
+        for (n, card) in order.into_iter().enumerate() {
+            let result = results[card];
+            let place = match (ranking.last(), result) {
+                (_, None) => None,
+                (Some(prev), Some((round, _))) if prev.round == Some(round) => prev.place,
+                _ => Some(n + 1),
+            };
+            ranking.push(Placing {
+                place,
+                card,
+                round: result.map(|(round, _)| round),
+                score: result.map(|(_, score)| score),
+            });
+        }
+
+        Timeline { turns, ranking }
+    }
+
+    // Plays `trials` games, each calling this game's numbers in a random order from an RNG
+    // seeded with `seed`, and tallies how each card does.
+    pub fn odds(&self, trials: usize, seed: u64) -> Odds {
+        let mut rng = Rng::new(seed);
+        // Numbers are shuffled and looked up by their place among the sorted calls rather than
+        // by value, which could be anything up to usize::MAX.
+        let mut numbers = self.calls.clone();
+        numbers.sort_unstable();
+        numbers.dedup();
+        let mut order: Vec<usize> = (0..numbers.len()).collect();
+        let mut called = vec![usize::MAX; numbers.len()];
+        let slots: Vec<Vec<Option<usize>>> = self.cards.iter()
+            .map(|card| card.squares.iter().map(|n| numbers.binary_search(n).ok()).collect())
+            .collect();
+
+        let cards = self.cards.len();
+        let mut first = vec![0.0; cards];
+        let mut last = vec![0.0; cards];
+        let mut rounds = vec![vec![0; order.len()]; cards];
+        let mut won: Vec<(usize, usize)> = Vec::with_capacity(cards);
+
+        for _ in 0..trials {
+            rng.shuffle(&mut order);
+            for (round, slot) in order.iter().enumerate() {
+                called[*slot] = round + 1;
+            }
+
+            won.clear();
+            for (n, card) in self.cards.iter().enumerate() {
+                let when = |square: usize| slots[n][square].map_or(usize::MAX, |i| called[i]);
+                if let Some(round) = card.winning_round_when(when) {
+                    rounds[n][round - 1] += 1;
+                    won.push((n, round));
+                }
+            }
+
+            let earliest = won.iter().map(|(_, round)| *round).min();
+            let latest = won.iter().map(|(_, round)| *round).max();
+            for (tally, when) in [(&mut first, earliest), (&mut last, latest)] {
+                if let Some(when) = when {
+                    let sharing = won.iter().filter(|(_, round)| *round == when).count();
+                    for (n, _) in won.iter().filter(|(_, round)| *round == when) {
+                        tally[*n] += 1.0 / sharing as f64;
                     }
                 }
             }
         }
 
-        Day4 { calls, cards }
+        let chance = |tally: Vec<f64>| tally.iter().map(|t| t / trials.max(1) as f64).collect();
+        Odds { trials, first: chance(first), last: chance(last), rounds }
+    }
+
+    // The shortest call order that makes `card` (numbered from 0) win first or last, on its
+    // own.  Only numbers on the cards are called.
+    pub fn rig(&self, card: usize, finish: Finish) -> Rigged {
+        let mut numbers: Vec<usize> = self.cards.iter()
+            .flat_map(|c| c.values.keys().copied())
+            .collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        let index: HashMap<usize, usize> =
+            numbers.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+
+        // Each card's lines as the numbers that must be called to complete them.
+        let lines: Vec<Vec<NumberSet>> = self.cards.iter()
+            .map(|c| {
+                c.lines.iter()
+                    .map(|line| {
+                        let mut set = NumberSet::new(numbers.len());
+                        for square in line.iter().filter(|square| Some(**square) != c.free) {
+                            set.insert(index[&c.squares[*square]]);
+                        }
+                        set
+                    })
+                    .collect()
+            })
+            .collect();
+        let target = &lines[card];
+        let others: Vec<&[NumberSet]> = lines.iter().enumerate()
+            .filter(|(n, _)| *n != card)
+            .map(|(_, l)| l.as_slice())
+            .collect();
+        let to_calls = |set: &NumberSet| -> Vec<usize> { set.iter().map(|i| numbers[i]).collect() };
+
+        match finish {
+            // Calling just one of its lines is as short as it gets, as long as that doesn't
+            // complete a line on another card too.
+            Finish::First => {
+                match target.iter()
+                    .filter(|line| others.iter().all(|c| c.iter().all(|l| !l.is_subset(line))))
+                    .min_by_key(|line| line.len()) {
+                    Some(line) => Rigged::Shortest(to_calls(line)),
+                    None => Rigged::Impossible,
+                }
+            }
+            Finish::Last => {
+                let mut search = LastSearch {
+                    target,
+                    others,
+                    last: 0,
+                    seen: HashSet::new(),
+                    best: None,
+                    steps: 0,
+                    limit: 0,
+                    gave_up: false,
+                };
+                // Each way of finishing the target gets a share of the steps, so each gets
+                // searched at least a little.
+                let mut starts = Vec::new();
+                for line in target {
+                    for last in line.iter() {
+                        let mut before = line.clone();
+                        before.remove(last);
+                        if !target.iter().any(|l| l.is_subset(&before)) {
+                            starts.push((last, before));
+                        }
+                    }
+                }
+                search.limit = RIG_SEARCH_LIMIT / starts.len().max(1);
+                for (last, before) in starts {
+                    search.last = last;
+                    search.seen.clear();
+                    search.steps = 0;
+                    search.search(before);
+                }
+                let calls = search.best.map(|(before, last)| {
+                    let mut calls = to_calls(&before);
+                    calls.push(numbers[last]);
+                    calls
+                });
+                match calls {
+                    _ if search.gave_up => Rigged::Unfinished(calls),
+                    Some(calls) => Rigged::Shortest(calls),
+                    None => Rigged::Impossible,
+                }
+            }
+        }
     }
 }
 
@@ -199,6 +745,37 @@ impl Day for Day4 {
 
         Ok(win_score as usize)
     }
+
+    fn explain(&self) -> Vec<(String, String)> {
+        // (card, round, score) of the first and last cards to win.
+        let mut first: Option<(usize, usize, usize)> = None;
+        let mut last: Option<(usize, usize, usize)> = None;
+
+        for (n, card) in self.cards.iter().enumerate() {
+            if let Some((round, score)) = card.play(&self.calls) {
+                match first {
+                    Some((_, first_round, _)) if first_round <= round => {}
+                    _ => first = Some((n, round, score)),
+                }
+                match last {
+                    Some((_, last_round, _)) if last_round >= round => {}
+                    _ => last = Some((n, round, score)),
+                }
+            }
+        }
+
+        let mut details = Vec::new();
+        for (label, result) in [("first", first), ("last", last)].iter() {
+            if let Some((card, round, score)) = result {
+                details.push((format!("{} winning card", label), (card + 1).to_string()));
+                details.push((format!("{} winning call", label),
+                              format!("{} (round {})", self.calls[round - 1], round)));
+                details.push((format!("{} winning score", label), score.to_string()));
+            }
+        }
+
+        details
+    }
 }
 
 #[cfg(test)]
@@ -213,6 +790,16 @@ mod tests {
         assert_eq!(d.cards.len(), 3);
     }
 
+    #[test]
+    fn test_explain() {
+        let d = Day4::load("data/day4_example1.txt");
+        let details = d.explain();
+        assert_eq!(details[0], (String::from("first winning card"), String::from("3")));
+        assert_eq!(details[1], (String::from("first winning call"), String::from("24 (round 12)")));
+        assert_eq!(details[3], (String::from("last winning card"), String::from("2")));
+        assert_eq!(details[5], (String::from("last winning score"), String::from("1924")));
+    }
+
     #[test]
     // Test results based on my inputs.  Yours will be different.
     fn test_part1() {
@@ -226,4 +813,279 @@ mod tests {
         let d = Day4::load("data/day4_example1.txt");
         assert_eq!(d.part2(), Ok(1924));
     }
+
+    // Rows of a 3x4 card numbered 1-12.
+    fn small_card() -> Vec<Vec<usize>> {
+        vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8], vec![9, 10, 11, 12]]
+    }
+
+    fn rules(patterns: &[WinPattern], free_centre: bool) -> WinRules {
+        WinRules { patterns: patterns.to_vec(), free_centre }
+    }
+
+    #[test]
+    fn test_rectangular() {
+        let card = BingoCard::new(small_card(), &WinRules::default()).unwrap();
+        assert_eq!(card.shape(), (3, 4));
+        assert_eq!(card.position(7), Some((2, 1)));
+        // Column 2 (2, 6, 10) wins on the fourth call, 99 isn't on the card.
+        assert_eq!(card.play(&[2, 99, 6, 10]), Some((4, (78 - 18) * 10)));
+        // A row takes 4.
+        assert_eq!(card.play(&[5, 6, 7, 8]), Some((4, 52 * 8)));
+        assert_eq!(card.play(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn test_patterns() {
+        let square = vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]];
+        let card = |r: &WinRules| BingoCard::new(square.clone(), r).unwrap();
+
+        let diagonals = rules(&[WinPattern::Diagonals], false);
+        assert_eq!(card(&diagonals).play(&[1, 2, 3, 5, 9]), Some((5, 25 * 9)));
+        assert_eq!(card(&diagonals).play(&[3, 5, 7]), Some((3, 30 * 7)));
+
+        let corners = rules(&[WinPattern::Corners], false);
+        assert_eq!(card(&corners).play(&[1, 5, 3, 7, 8, 9]), Some((6, 12 * 9)));
+
+        let blackout = rules(&[WinPattern::Blackout], true);
+        assert_eq!(card(&blackout).play(&[1, 2, 3, 4, 6, 7, 8]), None);
+        assert_eq!(card(&blackout).play(&[1, 2, 3, 4, 6, 7, 8, 9]), Some((8, 0)));
+
+        // The free centre counts toward a line, and calling it changes nothing.
+        let free = rules(&[WinPattern::Rows, WinPattern::Columns], true);
+        assert_eq!(card(&free).play(&[5, 4, 6]), Some((3, 30 * 6)));
+        assert_eq!(card(&free).play(&[2, 8]), Some((2, 30 * 8)));
+    }
+
+    #[test]
+    fn test_shape_checks() {
+        let diagonals = rules(&[WinPattern::Diagonals], false);
+        assert_eq!(BingoCard::new(small_card(), &diagonals).err().unwrap(),
+                   "diagonals need a square card, not 3x4");
+        assert_eq!(BingoCard::new(small_card(), &rules(&[WinPattern::Rows], true)).err().unwrap(),
+                   "a 3x4 card has no centre square");
+        assert_eq!(BingoCard::new(vec![vec![1, 2], vec![3]], &WinRules::default()).err().unwrap(),
+                   "row 2 has 1 numbers, expected 2");
+        let twice = vec![vec![1, 2], vec![2, 3]];
+        assert_eq!(BingoCard::new(twice, &WinRules::default()).err().unwrap(),
+                   "2 is on the card twice");
+
+        let defaults = WinRules::default();
+        assert_eq!(Day4::parse("1,2\n\n1 2\n3 4\n\n5 6 7\n8 9 10\n", &defaults).err().unwrap(),
+                   "card at line 6 is 2x3, the first card is 2x2");
+        assert_eq!(Day4::parse("1,2\n\n1 2\n3 4 5\n", &defaults).err().unwrap(),
+                   "card at line 3: row 2 has 3 numbers, expected 2");
+        assert_eq!(Day4::parse("1,x\n", &defaults).err().unwrap(), "line 1: bad call 'x'");
+        assert_eq!(Day4::parse("1\n\n1 2\n3 y\n", &defaults).err().unwrap(),
+                   "line 4: bad number 'y'");
+
+        let d = Day4::parse("3,1,4\n\n1 2\n3 4\n\n4 3\n2 1\n", &defaults).unwrap();
+        assert_eq!((d.calls().len(), d.cards().len()), (3, 2));
+        // Both cards win on the second call, the first one listed counts.
+        assert_eq!(d.part1(), Ok(6));
+    }
+
+    #[test]
+    fn test_winning_round() {
+        let d = Day4::load("data/day4_example1.txt");
+        let mut called = vec![usize::MAX; 100];
+        for (round, number) in d.calls.iter().enumerate() {
+            called[*number] = round + 1;
+        }
+        for card in &d.cards {
+            let played = card.play(&d.calls).map(|(round, _)| round);
+            assert_eq!(card.winning_round_when(|square| called[card.squares[square]]), played);
+        }
+    }
+
+    #[test]
+    fn test_odds() {
+        // Two cards, neither favoured, and a third that needs every number.
+        let rows = WinRules { patterns: vec![WinPattern::Rows], free_centre: false };
+        let d = Day4::parse("1,2,3,4\n\n1 2\n\n3 4\n\n1 9\n", &rows).unwrap();
+        let odds = d.odds(20_000, 7);
+        assert_eq!(odds, d.odds(20_000, 7));
+        assert_ne!(odds, d.odds(20_000, 8));
+        // Huge call numbers are fine.
+        let huge = Day4::parse("1,1000000000000\n\n1\n\n1000000000000\n", &rows).unwrap();
+        let rounds = huge.odds(100, 7).rounds;
+        assert_eq!((rounds[0][0] + rounds[0][1], rounds[0][0]), (100, rounds[1][1]));
+
+        assert!((odds.first[0] - 0.5).abs() < 0.02, "{:?}", odds.first);
+        assert!((odds.first[0] + odds.first[1] - 1.0).abs() < 1e-9);
+        assert_eq!(odds.first[2], 0.0);
+        assert_eq!(odds.rounds[2], vec![0, 0, 0, 0]);
+        assert_eq!(odds.mean_round(2), None);
+
+        // Card 1 wins in round 2 when 1 and 2 are called first (1 in 6 orders), round 3 when
+        // they're in the first three (2 in 6), else round 4.
+        let wins: usize = odds.rounds[0].iter().sum();
+        assert_eq!(wins, 20_000);
+        assert!((odds.rounds[0][1] as f64 / 20_000.0 - 1.0 / 6.0).abs() < 0.02);
+        assert!((odds.mean_round(0).unwrap() - 10.0 / 3.0).abs() < 0.05);
+        let table = odds.to_string();
+        assert_eq!(table.lines().count(), 5);
+        assert_eq!(table.lines().last(), Some("   3    0.0%    0.0%           -"));
+    }
+
+    #[test]
+    fn test_timeline() {
+        let d = Day4::load("data/day4_example1.txt");
+        let timeline = d.timeline();
+        assert_eq!(timeline.turns.len(), 27);
+
+        // 7 is on all three cards.
+        let first = &timeline.turns[0];
+        assert_eq!((first.round, first.call), (1, 7));
+        assert_eq!(first.marks[0], Mark { card: 0, col: 4, row: 2 });
+        assert_eq!(first.marks.len(), 3);
+
+        assert_eq!(timeline.turns[11].wins, vec![Win { card: 2, score: 4512 }]);
+        let places: Vec<(Option<usize>, usize, Option<usize>)> = timeline.ranking.iter()
+            .map(|p| (p.place, p.card, p.round))
+            .collect();
+        assert_eq!(places,
+                   vec![(Some(1), 2, Some(12)), (Some(2), 0, Some(14)), (Some(3), 1, Some(15))]);
+
+        // Card 3 is out after winning: nothing is marked on it later.
+        assert!(timeline.turns[12..].iter().all(|t| t.marks.iter().all(|m| m.card != 2)));
+
+        let json = timeline.to_json();
+        assert!(json.contains("\"ranking\""));
+        assert!(timeline.to_string()
+            .contains("round 12: 24 marked on 3 card(s), card 3 wins (4512)"));
+    }
+
+    // Plays calls and returns the round each card wins in.
+    fn rounds(d: &Day4, calls: &[usize]) -> Vec<Option<usize>> {
+        d.cards.iter().map(|c| c.play(calls).map(|(round, _)| round)).collect()
+    }
+
+    // A finished rig(), as the calls or None if it's impossible.
+    fn rigged(d: &Day4, card: usize, finish: Finish) -> Option<Vec<usize>> {
+        match d.rig(card, finish) {
+            Rigged::Shortest(calls) => Some(calls),
+            Rigged::Impossible => None,
+            Rigged::Unfinished(_) => panic!("search limit reached"),
+        }
+    }
+
+    #[test]
+    fn test_rig() {
+        let d = Day4::load("data/day4_example1.txt");
+        for card in 0..3 {
+            let calls = rigged(&d, card, Finish::First).unwrap();
+            assert_eq!(calls.len(), 5);
+            let won = rounds(&d, &calls);
+            assert_eq!(won[card], Some(5));
+            assert!(won.iter().enumerate().all(|(n, r)| n == card || r.is_none()), "{:?}", won);
+
+            let calls = rigged(&d, card, Finish::Last).unwrap();
+            let won = rounds(&d, &calls);
+            assert_eq!(won[card], Some(calls.len()));
+            assert!(won.iter().enumerate().all(|(n, r)| n == card || r.unwrap() < calls.len()));
+        }
+
+        // The same lines in a different order: they can only win together.
+        let defaults = WinRules::default();
+        let d = Day4::parse("1\n\n1 2\n3 4\n\n4 3\n2 1\n\n5 6\n7 8\n", &defaults).unwrap();
+        assert_eq!(rigged(&d, 0, Finish::First), None);
+        assert_eq!(rigged(&d, 0, Finish::Last), None);
+        assert_eq!(rigged(&d, 2, Finish::First), Some(vec![5, 6]));
+        assert_eq!(rigged(&d, 2, Finish::Last), Some(vec![1, 2, 6, 5]));
+
+        // Cards 1 and 3 have the same numbers, card 2 shares one with each.
+        let rows = rules(&[WinPattern::Rows], false);
+        let d = Day4::parse("1\n\n1 2\n\n1 3\n\n2 1\n", &rows).unwrap();
+        assert_eq!(rigged(&d, 0, Finish::First), None);
+        assert_eq!(rigged(&d, 2, Finish::Last), None);
+        assert_eq!(rigged(&d, 1, Finish::First), Some(vec![1, 3]));
+        assert_eq!(rigged(&d, 1, Finish::Last), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_rig_shortest() {
+        // Random small games against trying every set of calls, with the last call varied.
+        let mut rng = Rng::new(44);
+        let rules = rules(&[WinPattern::Rows, WinPattern::Columns, WinPattern::Diagonals], false);
+        for _ in 0..30 {
+            let mut text = String::from("1\n");
+            for _ in 0..4 {
+                let mut card: Vec<usize> = (1..=10).collect();
+                rng.shuffle(&mut card);
+                text.push_str(&format!("\n{} {}\n{} {}\n", card[0], card[1], card[2], card[3]));
+            }
+            let d = Day4::parse(&text, &rules).unwrap();
+
+            // Shortest [first, last] for each card.
+            let mut shortest = [[None; 2]; 4];
+            for subset in 1u32..1 << 10 {
+                let numbers: Vec<usize> = (1..=10).filter(|n| subset & 1 << (n - 1) != 0).collect();
+                for last in &numbers {
+                    let mut calls: Vec<usize> =
+                        numbers.iter().copied().filter(|n| n != last).collect();
+                    calls.push(*last);
+                    let won = rounds(&d, &calls);
+                    let Some(card) = won.iter().position(|r| *r == Some(calls.len())) else {
+                        continue;
+                    };
+                    let others = (0..4).filter(|n| *n != card);
+                    let first = others.clone().all(|n| won[n].is_none());
+                    let last = others.clone().all(|n| won[n].is_some_and(|r| r < calls.len()));
+                    for (finish, fits) in [first, last].iter().enumerate() {
+                        let best: &mut Option<usize> = &mut shortest[card][finish];
+                        if *fits && best.is_none_or(|s| calls.len() < s) {
+                            *best = Some(calls.len());
+                        }
+                    }
+                }
+            }
+
+            for (card, best) in shortest.iter().enumerate() {
+                for (finish, best) in [Finish::First, Finish::Last].iter().zip(best) {
+                    let calls = rigged(&d, card, *finish);
+                    assert_eq!(calls.as_ref().map(|c| c.len()), *best, "{}", text);
+                    if let Some(calls) = calls {
+                        assert_eq!(rounds(&d, &calls)[card], Some(calls.len()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rig_limit() {
+        // Too big to search through: it settles for a call order that works.
+        let mut rng = Rng::new(4);
+        let mut text = String::from("1\n");
+        for _ in 0..20 {
+            let mut numbers: Vec<usize> = (0..60).collect();
+            rng.shuffle(&mut numbers);
+            for row in numbers[..25].chunks(5) {
+                let row: Vec<String> = row.iter().map(|n| n.to_string()).collect();
+                text.push_str(&format!("\n{}", row.join(" ")));
+            }
+            text.push('\n');
+        }
+        let d = Day4::load_str(&text);
+        let calls = match d.rig(7, Finish::Last) {
+            Rigged::Unfinished(Some(calls)) => calls,
+            other => panic!("{:?}", other),
+        };
+        let won = rounds(&d, &calls);
+        assert_eq!(won[7], Some(calls.len()));
+        assert!(won.iter().enumerate().all(|(n, r)| n == 7 || r.unwrap() < calls.len()));
+    }
+
+    #[test]
+    fn test_ranking_ties() {
+        let rows = WinRules { patterns: vec![WinPattern::Rows], free_centre: false };
+        let d = Day4::parse("1,2,3\n\n1 2\n\n2 1\n\n3 4\n\n2 3\n", &rows).unwrap();
+        let ranking = d.timeline().ranking;
+        let places: Vec<(Option<usize>, usize)> =
+            ranking.iter().map(|p| (p.place, p.card)).collect();
+        assert_eq!(places, vec![(Some(1), 0), (Some(1), 1), (Some(3), 3), (None, 2)]);
+        assert_eq!(ranking[3].score, None);
+        assert_eq!(ranking[2].score, Some(0));
+    }
 }
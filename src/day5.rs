@@ -1,7 +1,6 @@
-use crate::day::Day;
+use crate::day::{Day, Param};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
 
 // use lazy_static::lazy_static;
 use regex::Regex;
@@ -43,45 +42,720 @@ impl Map {
     }
 }
 
+// Image formats for Day5::heatmap().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    // Plain greyscale, each point's value is its overlap count.
+    Pgm,
+    // Plain colour, white where there are no vents then yellow to dark red as overlaps grow.
+    Ppm,
+    // The colour heatmap with the vent lines drawn over it.
+    Svg,
+}
+
+// Most points a heatmap covers, about 4000×4000.  Larger vent maps would make images of
+// gigabytes, or take as long to write.
+pub const HEATMAP_MAX_POINTS: usize = 16_000_000;
+
+// Longest side of a heatmap SVG, in pixels.
+const SVG_SIZE: f64 = 800.0;
+
+// Colour for an overlap count out of max, from the stops of a yellow-orange-red scale.
+fn heat_colour(count: usize, max: usize) -> (u8, u8, u8) {
+    const STOPS: [(f64, f64, f64); 3] = [(255.0, 237.0, 160.0), (253.0, 141.0, 60.0),
+                                          (189.0, 0.0, 38.0)];
+    if count == 0 {
+        return (255, 255, 255);
+    }
+    let t = if max > 1 { (count - 1) as f64 / (max - 1) as f64 } else { 0.0 };
+    let at = t * (STOPS.len() - 1) as f64;
+    let (from, to) = (STOPS[at.floor() as usize], STOPS[(at.ceil() as usize).min(STOPS.len() - 1)]);
+    let f = at.fract();
+    let mix = |a: f64, b: f64| (a + (b - a) * f).round() as u8;
+
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// Writes values separated by spaces, keeping lines within the 70 characters plain PNM allows.
+fn pnm_values(out: &mut String, values: impl Iterator<Item = usize>) {
+    let mut line = 0;
+    for value in values {
+        let word = value.to_string();
+        if line > 0 && line + 1 + word.len() > 70 {
+            out.push('\n');
+            line = 0;
+        } else if line > 0 {
+            out.push(' ');
+            line += 1;
+        }
+        out.push_str(&word);
+        line += word.len();
+    }
+    out.push('\n');
+}
+
+// Number of overlapping lines that makes a point dangerous.
+const THRESHOLD: usize = 2;
+
+pub const PARAMS: &[Param] = &[
+    Param {
+        name: "threshold",
+        help: "overlapping lines that make a point dangerous",
+        defaults: (Some(THRESHOLD), Some(THRESHOLD)),
+    },
+    Param {
+        name: "bresenham",
+        help: "1 to draw sloping lines with Bresenham's algorithm, 0 for exact points",
+        defaults: (None, Some(0)),
+    },
+    Param {
+        name: "sweep",
+        help: "1 to count overlaps without a map, for huge coordinates",
+        defaults: (Some(0), Some(0)),
+    },
+];
+
+// How lines that aren't horizontal, vertical or at 45 degrees are drawn.  Those three come
+// out the same either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Raster {
+    // Only the points exactly on the line.
+    Lattice,
+    // Bresenham's algorithm: the nearest point in every column (or row, for steep lines).
+    Bresenham,
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// Bresenham's line algorithm, for any direction.
+struct Bresenham {
+    x: i64,
+    y: i64,
+    end: (i64, i64),
+    // Distances to go, dy negated, and directions.
+    dx: i64,
+    dy: i64,
+    sx: i64,
+    sy: i64,
+    err: i64,
+    done: bool,
+}
+
+impl Iterator for Bresenham {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.done {
+            return None;
+        }
+        let point = (self.x as usize, self.y as usize);
+        if (self.x, self.y) == self.end {
+            self.done = true;
+        } else {
+            let e2 = 2 * self.err;
+            if e2 >= self.dy {
+                self.err += self.dy;
+                self.x += self.sx;
+            }
+            if e2 <= self.dx {
+                self.err += self.dx;
+                self.y += self.sy;
+            }
+        }
+
+        Some(point)
+    }
+}
+
+// Points a line covers, from (x1, y1) to (x2, y2) inclusive, each once.
+fn line_points(line: (usize, usize, usize, usize), raster: Raster)
+               -> Box<dyn Iterator<Item = (usize, usize)>> {
+    let (x1, y1, x2, y2) = (line.0 as i64, line.1 as i64, line.2 as i64, line.3 as i64);
+    let (dx, dy) = (x2 - x1, y2 - y1);
+
+    match raster {
+        Raster::Lattice => {
+            // Lattice points on the line are evenly spaced, gcd(dx, dy) steps apart.
+            let steps = gcd(dx.abs(), dy.abs());
+            let (sx, sy) = if steps == 0 { (0, 0) } else { (dx / steps, dy / steps) };
+            Box::new((0..=steps).map(move |i| ((x1 + i * sx) as usize, (y1 + i * sy) as usize)))
+        }
+        Raster::Bresenham => Box::new(Bresenham {
+            x: x1,
+            y: y1,
+            end: (x2, y2),
+            dx: dx.abs(),
+            dy: -dy.abs(),
+            sx: dx.signum(),
+            sy: dy.signum(),
+            err: dx.abs() - dy.abs(),
+            done: false,
+        }),
+    }
+}
+
+// A stretch of one straight line where the same number of vent lines overlap, so overlaps
+// can be counted without drawing every point.  Covers start + i * step for i in 0..=len.
+#[derive(Clone, Debug)]
+struct Run {
+    // Which straight line it's on.  Runs on the same one never share points.
+    line: usize,
+    start: (i64, i64),
+    // Smallest whole-number step along the line, (0, 0) for a single point.
+    step: (i64, i64),
+    len: i64,
+    count: usize,
+}
+
+impl Run {
+    fn end(&self) -> (i64, i64) {
+        (self.start.0 + self.len * self.step.0, self.start.1 + self.len * self.step.1)
+    }
+
+    // ((min x, max x), (min y, max y))
+    fn bounds(&self) -> ((i64, i64), (i64, i64)) {
+        let end = self.end();
+        ((self.start.0.min(end.0), self.start.0.max(end.0)),
+         (self.start.1.min(end.1), self.start.1.max(end.1)))
+    }
+
+    fn covers(&self, p: (i64, i64)) -> bool {
+        let (dx, dy) = (p.0 - self.start.0, p.1 - self.start.1);
+        let (sx, sy) = self.step;
+        if (sx, sy) == (0, 0) {
+            return (dx, dy) == (0, 0);
+        }
+        if dx as i128 * sy as i128 != dy as i128 * sx as i128 {
+            return false;
+        }
+        // On the line, and the step is as small as it gets, so p is a whole number of steps
+        // from the start.
+        let t = if sx != 0 { dx / sx } else { dy / sy };
+        (0..=self.len).contains(&t)
+    }
+}
+
+// The point two runs on different lines share, if any.
+fn crossing(a: &Run, b: &Run) -> Option<(i64, i64)> {
+    if a.step == (0, 0) || b.step == (0, 0) {
+        let (point, other) = if a.step == (0, 0) { (a, b) } else { (b, a) };
+        return if other.covers(point.start) { Some(point.start) } else { None };
+    }
+
+    // Each line is step.y * x - step.x * y = c, solved by Cramer's rule.
+    let (ux1, uy1) = (a.step.0 as i128, a.step.1 as i128);
+    let (ux2, uy2) = (b.step.0 as i128, b.step.1 as i128);
+    let c1 = uy1 * a.start.0 as i128 - ux1 * a.start.1 as i128;
+    let c2 = uy2 * b.start.0 as i128 - ux2 * b.start.1 as i128;
+    let det = ux1 * uy2 - uy1 * ux2;
+    if det == 0 {
+        return None;
+    }
+    let (x, y) = (ux1 * c2 - ux2 * c1, uy1 * c2 - uy2 * c1);
+    if x % det != 0 || y % det != 0 {
+        return None;
+    }
+
+    let p = ((x / det) as i64, (y / det) as i64);
+    if a.covers(p) && b.covers(p) { Some(p) } else { None }
+}
+
+// A straight line as its step and the constant c in step.y * x - step.x * y = c.
+type LineKey = ((i64, i64), i128);
+
+// Vent lines along one straight line, as ranges of steps from a point on it.
+struct Collinear {
+    origin: (i64, i64),
+    ranges: Vec<(i64, i64)>,
+}
+
+// Merges lines lying along the same straight line, splitting them into runs wherever the
+// overlap count changes.
+fn runs(lines: &[(usize, usize, usize, usize)]) -> Vec<Run> {
+    let mut along: HashMap<LineKey, Collinear> = HashMap::new();
+    for line in lines {
+        let (x1, y1, x2, y2) = (line.0 as i64, line.1 as i64, line.2 as i64, line.3 as i64);
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let g = gcd(dx.abs(), dy.abs()).max(1);
+        let mut step = (dx / g, dy / g);
+        if step.0 < 0 || (step.0 == 0 && step.1 < 0) {
+            step = (-step.0, -step.1);
+        }
+        // A single point has no direction, its own coordinates keep it apart.
+        let key = if step == (0, 0) {
+            ((0, 0), ((x1 as i128) << 64) | y1 as i128)
+        } else {
+            (step, step.1 as i128 * x1 as i128 - step.0 as i128 * y1 as i128)
+        };
+
+        let group = along.entry(key)
+            .or_insert_with(|| Collinear { origin: (x1, y1), ranges: Vec::new() });
+        let origin = group.origin;
+        let steps_to = |x: i64, y: i64| match step {
+            (0, 0) => 0,
+            (sx, 0) => (x - origin.0) / sx,
+            (_, sy) => (y - origin.1) / sy,
+        };
+        let (a, b) = (steps_to(x1, y1), steps_to(x2, y2));
+        group.ranges.push((a.min(b), a.max(b)));
+    }
+
+    let mut runs = Vec::new();
+    for (line, ((step, _), group)) in along.into_iter().enumerate() {
+        let origin = group.origin;
+        let mut events: Vec<(i64, isize)> = Vec::new();
+        for (a, b) in group.ranges {
+            events.push((a, 1));
+            events.push((b + 1, -1));
+        }
+        events.sort_unstable();
+
+        let mut count: isize = 0;
+        for (n, (t, delta)) in events.iter().enumerate() {
+            count += delta;
+            let next = events.get(n + 1).map(|(t, _)| *t);
+            match next {
+                Some(next) if next > *t && count > 0 => runs.push(Run {
+                    line,
+                    start: (origin.0 + t * step.0, origin.1 + t * step.1),
+                    step,
+                    len: next - 1 - t,
+                    count: count as usize,
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    runs
+}
+
+// Largest coordinate a vent line can have, about 10^12.  The sweep works in i64, and where
+// two sloping lines cross in i128, which this keeps from overflowing.
+pub const MAX_COORD: usize = (1 << 40) - 1;
+
+// Side of the square cells VentIndex groups points into.
+const INDEX_CELL: usize = 16;
+
+// A region of the map, edges included.  One with left > right or top > bottom is empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+}
+
+impl Rect {
+    fn contains(&self, (x, y): (usize, usize)) -> bool {
+        (self.left..=self.right).contains(&x) && (self.top..=self.bottom).contains(&y)
+    }
+}
+
+// Covered points in one cell of the index.
+struct IndexCell {
+    // Each point and the lines through it, most covered first.
+    points: Vec<((usize, usize), Vec<usize>)>,
+}
+
+impl IndexCell {
+    fn max(&self) -> usize {
+        self.points.first().map_or(0, |(_, lines)| lines.len())
+    }
+}
+
+// The vent map split into cells, so questions about a point or region only look at the
+// cells it touches.  Lines are numbered by their place in Day5::lines().
+pub struct VentIndex {
+    cells: HashMap<(usize, usize), IndexCell>,
+}
+
+impl VentIndex {
+    fn new(lines: &[(usize, usize, usize, usize)], diagonals: bool, raster: Raster) -> VentIndex {
+        let mut through: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (n, line) in lines.iter().enumerate() {
+            let (x1, y1, x2, y2) = *line;
+            if !diagonals && x1 != x2 && y1 != y2 {
+                continue;
+            }
+            for pt in line_points(*line, raster) {
+                through.entry(pt).or_default().push(n);
+            }
+        }
+
+        let mut cells: HashMap<(usize, usize), IndexCell> = HashMap::new();
+        for (pt, lines) in through {
+            let cell = (pt.0 / INDEX_CELL, pt.1 / INDEX_CELL);
+            cells.entry(cell).or_insert_with(|| IndexCell { points: Vec::new() })
+                .points.push((pt, lines));
+        }
+        for cell in cells.values_mut() {
+            cell.points.sort_by(|(a, a_lines), (b, b_lines)| {
+                b_lines.len().cmp(&a_lines.len()).then(a.cmp(b))
+            });
+        }
+
+        VentIndex { cells }
+    }
+
+    // Cells overlapping the region, with whether they're entirely inside it.
+    fn cells_in(&self, region: &Rect) -> Vec<(&IndexCell, bool)> {
+        if region.left > region.right || region.top > region.bottom {
+            return Vec::new();
+        }
+        let (left, top) = (region.left / INDEX_CELL, region.top / INDEX_CELL);
+        let (right, bottom) = (region.right / INDEX_CELL, region.bottom / INDEX_CELL);
+        let inside = |(x, y): (usize, usize)| {
+            x * INDEX_CELL >= region.left && (x + 1) * INDEX_CELL - 1 <= region.right
+                && y * INDEX_CELL >= region.top && (y + 1) * INDEX_CELL - 1 <= region.bottom
+        };
+
+        // A big region is cheaper to check against the cells there are than to walk.
+        let area = (right - left + 1).saturating_mul(bottom - top + 1);
+        let mut found: Vec<((usize, usize), &IndexCell)> = if area > self.cells.len() {
+            self.cells.iter()
+                .filter(|((x, y), _)| (left..=right).contains(x) && (top..=bottom).contains(y))
+                .map(|(at, cell)| (*at, cell))
+                .collect()
+        } else {
+            (top..=bottom)
+                .flat_map(|y| (left..=right).map(move |x| (x, y)))
+                .filter_map(|at| self.cells.get(&at).map(|cell| (at, cell)))
+                .collect()
+        };
+        found.sort_by_key(|(at, _)| (at.1, at.0));
+
+        found.into_iter().map(|(at, cell)| (cell, inside(at))).collect()
+    }
+
+    // Lines covering a point.
+    pub fn lines_at(&self, x: usize, y: usize) -> Vec<usize> {
+        self.cells.get(&(x / INDEX_CELL, y / INDEX_CELL))
+            .and_then(|cell| cell.points.iter().find(|(pt, _)| *pt == (x, y)))
+            .map_or(Vec::new(), |(_, lines)| lines.clone())
+    }
+
+    // Number of lines covering a point.
+    pub fn count_at(&self, x: usize, y: usize) -> usize {
+        self.lines_at(x, y).len()
+    }
+
+    // Most lines covering any one point in the region, 0 if none do.
+    pub fn max_in(&self, region: &Rect) -> usize {
+        let mut max = 0;
+        for (cell, inside) in self.cells_in(region) {
+            if cell.max() <= max {
+                continue;
+            }
+            // Points are most covered first, so the first one inside is the cell's best.
+            let best = if inside {
+                Some(cell.max())
+            } else {
+                cell.points.iter().find(|(pt, _)| region.contains(*pt)).map(|(_, l)| l.len())
+            };
+            max = max.max(best.unwrap_or(0));
+        }
+
+        max
+    }
+
+    // Points in the region covered by at least thresh lines, with their counts, ordered by y
+    // then x.
+    pub fn points_at_least(&self, region: &Rect, thresh: usize) -> Vec<((usize, usize), usize)> {
+        let mut found = Vec::new();
+        for (cell, _) in self.cells_in(region) {
+            found.extend(cell.points.iter()
+                .take_while(|(_, lines)| lines.len() >= thresh)
+                .filter(|(pt, _)| region.contains(*pt))
+                .map(|(pt, lines)| (*pt, lines.len())));
+        }
+        found.sort_by_key(|((x, y), _)| (*y, *x));
+
+        found
+    }
+}
+
+// A vent map that lines can be added to and removed from, keeping counts up to date for both
+// parts as it goes rather than redrawing everything.
+pub struct VentMap {
+    raster: Raster,
+    // By id, None once removed.  Ids aren't reused.
+    lines: Vec<Option<(usize, usize, usize, usize)>>,
+    // Lines covering each point: (horizontal and vertical ones, all of them).
+    vents: HashMap<(usize, usize), (usize, usize)>,
+    // covered[mode][n] is the number of points covered by exactly n lines, mode 0 counting
+    // horizontal and vertical lines, mode 1 all lines.
+    covered: [Vec<usize>; 2],
+}
+
+impl VentMap {
+    pub fn new(raster: Raster) -> VentMap {
+        VentMap { raster, lines: Vec::new(), vents: HashMap::new(), covered: [vec![0], vec![0]] }
+    }
+
+    // Lines still on the map, with their ids.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, (usize, usize, usize, usize))> + '_ {
+        self.lines.iter().enumerate().filter_map(|(id, line)| line.map(|l| (id, l)))
+    }
+
+    // Moves a point's count for one mode from `from` lines to `to`.
+    fn recount(&mut self, mode: usize, from: usize, to: usize) {
+        let covered = &mut self.covered[mode];
+        if from > 0 {
+            covered[from] -= 1;
+        }
+        if to > 0 {
+            if covered.len() <= to {
+                covered.resize(to + 1, 0);
+            }
+            covered[to] += 1;
+        }
+    }
+
+    fn draw(&mut self, line: (usize, usize, usize, usize), delta: isize) {
+        let (x1, y1, x2, y2) = line;
+        let straight = x1 == x2 || y1 == y2;
+        for pt in line_points(line, self.raster) {
+            let (before_straight, before_all) = self.vents.get(&pt).copied().unwrap_or((0, 0));
+            let after_all = (before_all as isize + delta) as usize;
+            let after_straight = if straight {
+                (before_straight as isize + delta) as usize
+            } else {
+                before_straight
+            };
+
+            if straight {
+                self.recount(0, before_straight, after_straight);
+            }
+            self.recount(1, before_all, after_all);
+            if after_all == 0 {
+                self.vents.remove(&pt);
+            } else {
+                self.vents.insert(pt, (after_straight, after_all));
+            }
+        }
+    }
+
+    // Adds a line, returning its id.
+    pub fn add(&mut self, line: (usize, usize, usize, usize)) -> usize {
+        self.draw(line, 1);
+        self.lines.push(Some(line));
+        self.lines.len() - 1
+    }
+
+    // Takes a line off the map, None if there's no such line.
+    pub fn remove(&mut self, id: usize) -> Option<(usize, usize, usize, usize)> {
+        let line = self.lines.get_mut(id)?.take()?;
+        self.draw(line, -1);
+        Some(line)
+    }
+
+    // Lines covering a point, counting only horizontal and vertical ones unless diagonals.
+    pub fn count_at(&self, x: usize, y: usize, diagonals: bool) -> usize {
+        let (straight, all) = self.vents.get(&(x, y)).copied().unwrap_or((0, 0));
+        if diagonals { all } else { straight }
+    }
+
+    // Points covered by at least thresh lines, like the parts count them.
+    pub fn dangers(&self, thresh: usize, diagonals: bool) -> usize {
+        self.covered[diagonals as usize].iter().skip(thresh.max(1)).sum()
+    }
+}
+
 pub struct Day5 {
     // Vent lines represented as tuple of x1, y2, x2, y2
     lines: Vec<(usize, usize, usize, usize)>,
+    // Overrides THRESHOLD
+    threshold: Option<usize>,
+    raster: Raster,
+    // Count with dangers_by_sweep() instead of a map.
+    sweep: bool,
 }
 
 impl Day5 {
     pub fn load(filename: &str) -> Day5 {
-        // println!("Loading.");
+        Day5::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day5 {
+        Day5::parse(input).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Lines that aren't "x1,y1 -> x2,y2" are skipped.  Coordinates over MAX_COORD are errors.
+    pub fn parse(input: &str) -> Result<Day5, String> {
         lazy_static! {
             static ref LINE_RE: Regex =
                 Regex::new("([0-9]+),([0-9]+) -> ([0-9]+),([0-9]+)").unwrap();
         }
         let mut lines: Vec<(usize, usize, usize, usize)> = Vec::new();
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+        for (n, l) in input.lines().enumerate() {
+            let caps = match LINE_RE.captures(l) {
+                Some(caps) => caps,
+                // Blank line
+                None => continue,
+            };
+            let mut coords = [0; 4];
+            for (i, coord) in coords.iter_mut().enumerate() {
+                *coord = caps[i + 1].parse::<usize>().ok()
+                    .filter(|c| *c <= MAX_COORD)
+                    .ok_or_else(|| format!("line {}: coordinates go up to {}", n + 1, MAX_COORD))?;
+            }
+            lines.push((coords[0], coords[1], coords[2], coords[3]));
+        }
 
-        for line in reader.lines() {
-            let l = line.unwrap();
+        Ok(Day5 { lines, threshold: None, raster: Raster::Lattice, sweep: false })
+    }
 
-            let caps = LINE_RE.captures(&l);
-            match caps {
-                Some(caps) => {
-                    let line = (
-                        caps[1].parse::<usize>().unwrap(),
-                        caps[2].parse::<usize>().unwrap(),
-                        caps[3].parse::<usize>().unwrap(),
-                        caps[4].parse::<usize>().unwrap(),
-                    );
+    // Counts points covered by at least thresh lines, like gen_map(diagonals).dangers(thresh)
+    // but from where lines overlap and cross rather than every point, so the size of the
+    // coordinates doesn't matter.  Sloping lines cover their exact points.
+    //
+    // Each run is checked against every earlier one still overlapping it in x, so many long
+    // runs side by side (say thousands of long horizontal lines) take quadratic time.
+    pub fn dangers_by_sweep(&self, diagonals: bool, thresh: usize) -> usize {
+        let lines: Vec<(usize, usize, usize, usize)> = self.lines.iter()
+            .filter(|(x1, y1, x2, y2)| diagonals || x1 == x2 || y1 == y2)
+            .copied()
+            .collect();
+        let runs = runs(&lines);
+        let mut total: usize = runs.iter()
+            .filter(|r| r.count >= thresh)
+            .map(|r| r.len as usize + 1)
+            .sum();
 
-                    lines.push(line);
+        // Sweep left to right, checking each run against those whose x range it overlaps.
+        let mut order: Vec<usize> = (0..runs.len()).collect();
+        order.sort_by_key(|r| runs[*r].bounds().0);
+        let mut active: Vec<usize> = Vec::new();
+        let mut crossings: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for r in order {
+            let ((left, _), (bottom, top)) = runs[r].bounds();
+            active.retain(|a| runs[*a].bounds().0 .1 >= left);
+            for a in &active {
+                let (_, (a_bottom, a_top)) = runs[*a].bounds();
+                if runs[*a].line == runs[r].line || a_top < bottom || a_bottom > top {
+                    continue;
                 }
-                None => {
-                    // Blank line
+                if let Some(p) = crossing(&runs[*a], &runs[r]) {
+                    crossings.entry(p).or_default().extend([*a, r].iter());
                 }
             }
+            active.push(r);
+        }
+
+        // Where lines cross, the point was counted once for each run by its own count.
+        for (_, mut meeting) in crossings {
+            meeting.sort_unstable();
+            meeting.dedup();
+            let covered: usize = meeting.iter().map(|r| runs[*r].count).sum();
+            total -= meeting.iter().filter(|r| runs[**r].count >= thresh).count();
+            total += (covered >= thresh) as usize;
+        }
+
+        total
+    }
+
+    fn dangers(&self, diagonals: bool) -> Result<usize, &str> {
+        let thresh = self.threshold.unwrap_or(THRESHOLD);
+        if !self.sweep {
+            return Ok(self.gen_map(diagonals).dangers(thresh));
+        }
+        if diagonals && self.raster == Raster::Bresenham {
+            return Err("the sweep only counts exact points, not Bresenham's");
+        }
+
+        Ok(self.dangers_by_sweep(diagonals, thresh))
+    }
+
+    // Vent lines as (x1, y1, x2, y2).
+    pub fn lines(&self) -> &[(usize, usize, usize, usize)] {
+        &self.lines
+    }
+
+    // Index of the vent map for spatial queries, drawn the same way as the parts.
+    pub fn index(&self, diagonals: bool) -> VentIndex {
+        VentIndex::new(&self.lines, diagonals, self.raster)
+    }
+
+    // A vent map to edit, starting with these lines, whose ids are their places in lines().
+    pub fn vent_map(&self) -> VentMap {
+        let mut map = VentMap::new(self.raster);
+        for line in &self.lines {
+            map.add(*line);
         }
+        map
+    }
 
-        Day5 { lines }
+    // Smallest box around every line: (left, top, right, bottom).
+    fn bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let xs = self.lines.iter().flat_map(|(x1, _, x2, _)| [*x1, *x2]);
+        let ys = self.lines.iter().flat_map(|(_, y1, _, y2)| [*y1, *y2]);
+        Some((xs.clone().min()?, ys.clone().min()?, xs.max()?, ys.max()?))
+    }
+
+    // The whole vent map as an image, sized to fit the lines.  y runs down the image.  Fails
+    // if the lines spread over more than HEATMAP_MAX_POINTS points.
+    pub fn heatmap(&self, diagonals: bool, format: ImageFormat) -> Result<String, String> {
+        let (left, top, right, bottom) = self.bounds().unwrap_or((0, 0, 0, 0));
+        let (width, height) = ((right - left).saturating_add(1), (bottom - top).saturating_add(1));
+        if width.checked_mul(height).is_none_or(|points| points > HEATMAP_MAX_POINTS) {
+            return Err(format!("vent map is {}×{} points, more than the heatmap limit of {}",
+                               width, height, HEATMAP_MAX_POINTS));
+        }
+        let map = self.gen_map(diagonals);
+        let max = map.vents.values().max().copied().unwrap_or(0);
+        let count = |x: usize, y: usize| map.vents.get(&(left + x, top + y)).copied().unwrap_or(0);
+        let points = || (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)));
+
+        match format {
+            ImageFormat::Pgm => {
+                // Greys only go up to 65535, beyond that counts are scaled down.
+                let grey = max.clamp(1, 65535);
+                let mut pgm = format!("P2\n{} {}\n{}\n", width, height, grey);
+                pnm_values(&mut pgm, points().map(|(x, y)| count(x, y) * grey / max.max(1)));
+                Ok(pgm)
+            }
+            ImageFormat::Ppm => {
+                let mut ppm = format!("P3\n{} {}\n255\n", width, height);
+                let colours = points().flat_map(|(x, y)| {
+                    let (r, g, b) = heat_colour(count(x, y), max);
+                    [r as usize, g as usize, b as usize]
+                });
+                pnm_values(&mut ppm, colours);
+                Ok(ppm)
+            }
+            ImageFormat::Svg => {
+                // One unit per point, lines through the middle of their points.
+                let scale = SVG_SIZE / width.max(height) as f64;
+                let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" \
+                                       height=\"{:.0}\" viewBox=\"{} {} {} {}\">\n",
+                                      width as f64 * scale, height as f64 * scale,
+                                      left, top, width, height);
+                svg += &format!("  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                                 fill=\"white\"/>\n", left, top, width, height);
+                let mut covered: Vec<(&(usize, usize), &usize)> = map.vents.iter().collect();
+                covered.sort();
+                for ((x, y), n) in covered {
+                    let (r, g, b) = heat_colour(*n, max);
+                    svg += &format!("  <rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" \
+                                     fill=\"#{:02x}{:02x}{:02x}\"><title>{}</title></rect>\n",
+                                    x, y, r, g, b, n);
+                }
+                for (x1, y1, x2, y2) in &self.lines {
+                    if !diagonals && x1 != x2 && y1 != y2 {
+                        continue;
+                    }
+                    svg += &format!("  <line x1=\"{}.5\" y1=\"{}.5\" x2=\"{}.5\" y2=\"{}.5\" \
+                                     stroke=\"steelblue\" stroke-width=\"{:.3}\"/>\n",
+                                    x1, y1, x2, y2, 1.5 / scale);
+                }
+                svg += "</svg>\n";
+                Ok(svg)
+            }
+        }
     }
 
     // generate a map with count of vent lines crossing each coordinate
@@ -97,39 +771,8 @@ impl Day5 {
             }
 
             // add points to the map
-            let x_incr = if x1 == x2 {
-                0
-            } else if x1 < x2 {
-                1
-            } else {
-                -1
-            };
-            let y_incr = if y1 == y2 {
-                0
-            } else if y1 < y2 {
-                1
-            } else {
-                -1
-            };
-
-            let mut x = x1 as isize;
-            let mut y = y1 as isize;
-            let mut pt = (x as usize, y as usize);
-            if vents.contains_key(&pt) {
-                vents.insert(pt, vents.get(&pt).unwrap() + 1);
-            } else {
-                vents.insert(pt, 1);
-            }
-
-            while !((x == x2 as isize) && (y == y2 as isize)) {
-                x += x_incr;
-                y += y_incr;
-                pt = (x as usize, y as usize);
-                if vents.contains_key(&pt) {
-                    vents.insert(pt, vents.get(&pt).unwrap() + 1);
-                } else {
-                    vents.insert(pt, 1);
-                }
+            for pt in line_points(*line, self.raster) {
+                *vents.entry(pt).or_insert(0) += 1;
             }
         }
 
@@ -139,25 +782,46 @@ impl Day5 {
 
 impl Day for Day5 {
     fn part1(&self) -> Result<usize, &str> {
-        let map = self.gen_map(false);
-        // map.display();
-        let dangers = map.dangers(2);
-
-        Ok(dangers)
+        self.dangers(false)
     }
 
     fn part2(&self) -> Result<usize, &str> {
-        let map = self.gen_map(true);
-        // map.display();
-        let dangers = map.dangers(2);
+        self.dangers(true)
+    }
 
-        Ok(dangers)
+    fn params(&self) -> Vec<Param> {
+        PARAMS.to_vec()
+    }
+
+    fn set_param(&mut self, name: &str, value: usize) -> Result<(), String> {
+        match name {
+            "threshold" if value == 0 => Err(String::from("threshold must be at least 1")),
+            "threshold" => {
+                self.threshold = Some(value);
+                Ok(())
+            }
+            "bresenham" => {
+                self.raster = match value {
+                    0 => Raster::Lattice,
+                    1 => Raster::Bresenham,
+                    _ => return Err(String::from("bresenham must be 0 or 1")),
+                };
+                Ok(())
+            }
+            "sweep" if value <= 1 => {
+                self.sweep = value == 1;
+                Ok(())
+            }
+            "sweep" => Err(String::from("sweep must be 0 or 1")),
+            _ => Err(format!("unknown parameter: {}", name)),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rng::Rng;
     //use crate::day::Day;
 
     #[test]
@@ -177,4 +841,229 @@ mod tests {
         let d = Day5::load("data/day5_example1.txt");
         assert_eq!(d.part2(), Ok(12));
     }
+
+    #[test]
+    fn test_line_points() {
+        let points = |line, raster| line_points(line, raster).collect::<Vec<_>>();
+        assert_eq!(points((6, 4, 0, 0), Raster::Lattice), vec![(6, 4), (3, 2), (0, 0)]);
+        assert_eq!(points((0, 0, 6, 4), Raster::Bresenham),
+                   vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 3), (5, 3), (6, 4)]);
+        // Steep, one point per row.
+        assert_eq!(points((2, 0, 1, 3), Raster::Bresenham),
+                   vec![(2, 0), (2, 1), (1, 2), (1, 3)]);
+        assert_eq!(points((2, 0, 1, 3), Raster::Lattice), vec![(2, 0), (1, 3)]);
+        assert_eq!(points((5, 5, 5, 5), Raster::Bresenham), vec![(5, 5)]);
+
+        // The puzzle's lines come out the same either way.
+        let d = Day5::load("data/day5_example1.txt");
+        for line in &d.lines {
+            assert_eq!(points(*line, Raster::Lattice), points(*line, Raster::Bresenham));
+        }
+    }
+
+    #[test]
+    fn test_any_angle() {
+        // The sloping line crosses the vertical one at (3, 2), and with Bresenham it's drawn
+        // through (4, 3) and (5, 3) on the horizontal one too.
+        let mut d = Day5::load_str("0,0 -> 6,4\n3,0 -> 3,5\n0,3 -> 6,3\n");
+        assert_eq!(d.part1(), Ok(1));
+        assert_eq!(d.part2(), Ok(2));
+        d.set_param("bresenham", 1).unwrap();
+        assert_eq!(d.part2(), Ok(4));
+        assert!(d.set_param("bresenham", 2).is_err());
+    }
+
+    #[test]
+    fn test_sweep() {
+        let d = Day5::load("data/day5_example1.txt");
+        for thresh in 0..4 {
+            for diagonals in [false, true].iter() {
+                assert_eq!(d.dangers_by_sweep(*diagonals, thresh),
+                           d.gen_map(*diagonals).dangers(thresh));
+            }
+        }
+
+        // Random lines at all sorts of angles, overlapping and crossing, some just points.
+        let mut rng = Rng::new(5);
+        for _ in 0..50 {
+            let mut input = String::new();
+            for _ in 0..rng.below(30) {
+                let (x, y) = (rng.below(20), rng.below(20));
+                let (x2, y2) = match rng.below(4) {
+                    0 => (rng.below(20), y),
+                    1 => (x, rng.below(20)),
+                    2 => {
+                        let d = rng.below(20 - x.max(y));
+                        (x + d, y + d)
+                    }
+                    _ => (rng.below(20), rng.below(20)),
+                };
+                input.push_str(&format!("{},{} -> {},{}\n", x, y, x2, y2));
+            }
+            let d = Day5::load_str(&input);
+            for thresh in 1..5 {
+                assert_eq!(d.dangers_by_sweep(true, thresh), d.gen_map(true).dangers(thresh),
+                           "threshold {}\n{}", thresh, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sweep_huge() {
+        // A long diagonal crossed by a long row, overlapped in the middle by a shorter diagonal,
+        // and a line it meets between whole coordinates.
+        let mut d = Day5::load_str("0,0 -> 1000000000,1000000000\n\
+                                    0,500000000 -> 2000000000,500000000\n\
+                                    250000000,250000000 -> 750000000,750000000\n\
+                                    0,1 -> 1,0\n");
+        d.set_param("sweep", 1).unwrap();
+        assert_eq!(d.part1(), Ok(0));
+        assert_eq!(d.part2(), Ok(500000001));
+        d.set_param("threshold", 3).unwrap();
+        assert_eq!(d.part2(), Ok(1));
+        assert!(d.set_param("threshold", 0).is_err());
+        d.set_param("bresenham", 1).unwrap();
+        assert!(d.part2().is_err());
+
+        // Steep lines as long as they get, which only meet between whole coordinates.
+        let max = MAX_COORD;
+        let input = format!("0,0 -> {},{}\n0,{} -> {},0\n", max, max - 1, max, max - 1);
+        let d = Day5::load_str(&input);
+        assert_eq!((d.dangers_by_sweep(true, 1), d.dangers_by_sweep(true, 2)), (4, 0));
+        assert_eq!(Day5::parse(&format!("0,0 -> {},0\n", max + 1)).err().unwrap(),
+                   format!("line 1: coordinates go up to {}", max));
+        assert!(Day5::parse("1,1 -> 1,1\n0,0 -> 0,99999999999999999999999\n").is_err());
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let d = Day5::load("data/day5_example1.txt");
+        let pgm = d.heatmap(true, ImageFormat::Pgm).unwrap();
+        let mut lines = pgm.lines();
+        assert_eq!((lines.next(), lines.next(), lines.next()),
+                   (Some("P2"), Some("10 10"), Some("3")));
+        // First row of the puzzle's diagram: 1.1....11.
+        let values: Vec<&str> = pgm.split_whitespace().skip(4).collect();
+        assert_eq!(values.len(), 100);
+        assert_eq!(values[..10], ["1", "0", "1", "0", "0", "0", "0", "1", "1", "0"]);
+        assert!(pgm.lines().all(|l| l.len() <= 70));
+
+        let ppm = d.heatmap(false, ImageFormat::Ppm).unwrap();
+        assert!(ppm.starts_with("P3\n10 10\n255\n255 255 255 255 255 255 255 255 255 255 255 255"));
+        assert_eq!(ppm.split_whitespace().count(), 4 + 300);
+        assert_eq!(heat_colour(1, 1), (255, 237, 160));
+        assert_eq!(heat_colour(3, 3), (189, 0, 38));
+        assert_eq!(heat_colour(2, 3), (253, 141, 60));
+
+        let svg = d.heatmap(true, ImageFormat::Svg).unwrap();
+        assert!(svg.contains("viewBox=\"0 0 10 10\""));
+        assert_eq!(svg.matches("<line").count(), 10);
+        assert_eq!(svg.matches("<title>").count(), d.gen_map(true).vents.len());
+        assert_eq!(d.heatmap(false, ImageFormat::Svg).unwrap().matches("<line").count(), 6);
+
+        // Sized to the lines, wherever they are.
+        let d = Day5::load_str("100,50 -> 103,50\n101,49 -> 101,52\n");
+        let pgm = d.heatmap(true, ImageFormat::Pgm).unwrap();
+        assert_eq!(pgm, "P2\n4 4\n2\n0 1 0 0 1 2 1 1 0 1 0 0 0 1 0 0\n");
+
+        // Too spread out to draw.
+        let d = Day5::load_str("0,0 -> 4000,0\n0,4000 -> 0,4000\n");
+        assert!(d.heatmap(true, ImageFormat::Pgm).is_err());
+        let d = Day5::load_str(&format!("0,0 -> 0,0\n{},0 -> 0,0\n", MAX_COORD));
+        assert!(d.heatmap(false, ImageFormat::Svg).is_err());
+    }
+
+    #[test]
+    fn test_index() {
+        let d = Day5::load("data/day5_example1.txt");
+        let index = d.index(true);
+        // 9,4 -> 3,4 and 7,0 -> 7,4.
+        assert_eq!(index.lines_at(7, 4), vec![2, 4]);
+        assert_eq!(index.count_at(7, 4), 2);
+        assert_eq!(index.lines_at(9, 9), Vec::<usize>::new());
+        let all = Rect { left: 0, top: 0, right: 9, bottom: 9 };
+        assert_eq!(index.max_in(&all), 3);
+        assert_eq!(index.points_at_least(&all, 2).len(), 12);
+        assert_eq!(d.index(false).points_at_least(&all, 2).len(), 5);
+        let corner = Rect { left: 0, top: 0, right: 1, bottom: 1 };
+        assert_eq!(index.points_at_least(&corner, 1), vec![((0, 0), 1), ((1, 1), 1)]);
+        // Inverted regions are empty.
+        let inverted = Rect { left: 9, top: 0, right: 0, bottom: 9 };
+        assert_eq!(index.max_in(&inverted), 0);
+        let inverted = Rect { left: 0, top: 9, right: 9, bottom: 0 };
+        assert_eq!(index.points_at_least(&inverted, 1), vec![]);
+
+        // Random regions of a larger map against checking every point.
+        let mut rng = Rng::new(49);
+        let mut input = String::new();
+        for _ in 0..60 {
+            let (x, y, d) = (rng.below(80), rng.below(80), rng.below(40));
+            let (x2, y2) = match rng.below(3) {
+                0 => (x + d, y),
+                1 => (x, y + d),
+                _ => (x + d, y + d),
+            };
+            input.push_str(&format!("{},{} -> {},{}\n", x, y, x2, y2));
+        }
+        let d = Day5::load_str(&input);
+        let (map, index) = (d.gen_map(true), d.index(true));
+        for _ in 0..200 {
+            let (left, top) = (rng.below(120), rng.below(120));
+            let region = Rect { left, top, right: left + rng.below(60), bottom: top + rng.below(60) };
+            let mut inside: Vec<((usize, usize), usize)> = map.vents.iter()
+                .filter(|(pt, _)| region.contains(**pt))
+                .map(|(pt, n)| (*pt, *n))
+                .collect();
+            inside.sort_by_key(|((x, y), _)| (*y, *x));
+
+            assert_eq!(index.max_in(&region), inside.iter().map(|(_, n)| *n).max().unwrap_or(0));
+            let thresh = rng.below(4) + 1;
+            inside.retain(|(_, n)| *n >= thresh);
+            assert_eq!(index.points_at_least(&region, thresh), inside);
+        }
+        for (pt, n) in &map.vents {
+            assert_eq!(index.count_at(pt.0, pt.1), *n);
+        }
+    }
+
+    #[test]
+    fn test_vent_map() {
+        let d = Day5::load("data/day5_example1.txt");
+        let mut map = d.vent_map();
+        assert_eq!((map.dangers(2, false), map.dangers(2, true)), (5, 12));
+        assert_eq!(map.dangers(0, true), d.gen_map(true).vents.len());
+
+        // 8,0 -> 0,8 only counts with diagonals.
+        assert_eq!(map.remove(1), Some((8, 0, 0, 8)));
+        assert_eq!(map.remove(1), None);
+        assert_eq!(map.remove(10), None);
+        assert_eq!(map.dangers(2, false), 5);
+        // 9,4 -> 3,4 and 0,0 -> 8,8 are left there.
+        assert_eq!(map.count_at(4, 4, true), 2);
+        assert_eq!(map.count_at(4, 4, false), 1);
+        assert_eq!(map.add((8, 0, 0, 8)), 10);
+        assert_eq!(map.dangers(2, true), 12);
+
+        // Random edits against redrawing from scratch.
+        let mut rng = Rng::new(50);
+        let mut map = VentMap::new(Raster::Lattice);
+        for _ in 0..300 {
+            let ids: Vec<usize> = map.lines().map(|(id, _)| id).collect();
+            if !ids.is_empty() && rng.below(3) == 0 {
+                assert!(map.remove(ids[rng.below(ids.len())]).is_some());
+            } else {
+                map.add((rng.below(30), rng.below(30), rng.below(30), rng.below(30)));
+            }
+
+            let lines: Vec<String> = map.lines()
+                .map(|(_, (x1, y1, x2, y2))| format!("{},{} -> {},{}", x1, y1, x2, y2))
+                .collect();
+            let fresh = Day5::load_str(&lines.join("\n"));
+            for diagonals in [false, true].iter() {
+                let thresh = rng.below(4);
+                assert_eq!(map.dangers(thresh, *diagonals),
+                           fresh.gen_map(*diagonals).dangers(thresh));
+            }
+        }
+    }
 }
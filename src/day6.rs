@@ -1,30 +1,39 @@
-use crate::day::Day;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use crate::day::{Day, Param};
+use std::fs;
+
+// Generations simulated for part 1 and part 2.
+const GENERATIONS: (usize, usize) = (80, 256);
+
+pub const PARAMS: &[Param] = &[Param {
+    name: "generations",
+    help: "days of lanternfish growth to simulate",
+    defaults: (Some(GENERATIONS.0), Some(GENERATIONS.1)),
+}];
 
 pub struct Day6 {
     // Timers (Generations until spawning)
     timers: Vec<usize>,
+    // Overrides GENERATIONS for both parts
+    generations: Option<usize>,
 }
 
 impl Day6 {
     pub fn load(filename: &str) -> Day6 {
+        Day6::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day6 {
         // println!("Loading.");
         let mut timers: Vec<usize> = Vec::new();
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = line.unwrap();
-
+        for l in input.lines() {
             for s in l.split(',') {
                 let n = s.parse::<usize>().unwrap();
                 timers.push(n);
             }
         }
 
-        Day6 { timers }
+        Day6 { timers, generations: None }
     }
 
     #[allow(dead_code)]
@@ -63,7 +72,8 @@ impl Day6 {
         population.len()
     }
 
-    fn sim_smart(&self, generations: usize) -> usize {
+    // None if the population outgrows a usize.
+    fn sim_smart(&self, generations: usize) -> Option<usize> {
         let mut per_timer: [usize; 9] = [0; 9];
 
         // initialize per_timer counts based on initial population
@@ -82,29 +92,38 @@ impl Day6 {
             }
 
             // Take care of those spawning cases now.
-            per_timer[6] += spawns;
+            per_timer[6] = per_timer[6].checked_add(spawns)?;
             per_timer[8] = spawns;
 
             // println!("{:?}", per_timer);
         }
 
         // sum up the fish
-        let mut sum = 0;
-        for n in 0..9 {
-            sum += per_timer[n];
-        }
-
-        sum
+        per_timer.iter().try_fold(0usize, |sum, n| sum.checked_add(*n))
     }
 }
 
 impl Day for Day6 {
     fn part1(&self) -> Result<usize, &str> {
-        Ok(self.sim_smart(80))
+        self.sim_smart(self.generations.unwrap_or(GENERATIONS.0)).ok_or("fish count overflows")
     }
 
     fn part2(&self) -> Result<usize, &str> {
-        Ok(self.sim_smart(256))
+        self.sim_smart(self.generations.unwrap_or(GENERATIONS.1)).ok_or("fish count overflows")
+    }
+
+    fn params(&self) -> Vec<Param> {
+        PARAMS.to_vec()
+    }
+
+    fn set_param(&mut self, name: &str, value: usize) -> Result<(), String> {
+        match name {
+            "generations" => {
+                self.generations = Some(value);
+                Ok(())
+            }
+            _ => Err(format!("unknown parameter: {}", name)),
+        }
     }
 }
 
@@ -128,13 +147,26 @@ mod tests {
     #[test]
     fn test_sim_smart() {
         let d = Day6::load("data/day6_example1.txt");
-        assert_eq!(d.sim_smart(18), 26);
+        assert_eq!(d.sim_smart(18), Some(26));
     }
 
     #[test]
     fn test_sim_smart2() {
         let d = Day6::load("data/day6_example1.txt");
-        assert_eq!(d.sim_smart(256), 26984457539);
+        assert_eq!(d.sim_smart(256), Some(26984457539));
+    }
+
+    #[test]
+    fn test_set_param() {
+        let mut d = Day6::load_str("3,4,3,1,2");
+        assert_eq!(d.part1(), Ok(5934));
+        d.set_param("generations", 18).unwrap();
+        assert_eq!(d.part1(), Ok(26));
+        assert_eq!(d.part2(), Ok(26));
+        assert!(d.set_param("steps", 18).is_err());
+        d.set_param("generations", 1000).unwrap();
+        assert_eq!(d.part1(), Err("fish count overflows"));
+        assert_eq!(d.part2(), Err("fish count overflows"));
     }
 
     #[test]
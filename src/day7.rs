@@ -1,7 +1,6 @@
 use crate::day::Day;
 use std::cmp::min;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
 
 pub struct Day7 {
     positions: Vec<usize>,
@@ -9,14 +8,14 @@ pub struct Day7 {
 
 impl Day7 {
     pub fn load(filename: &str) -> Day7 {
+        Day7::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day7 {
         // println!("Loading.");
         let mut positions: Vec<usize> = Vec::new();
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = line.unwrap();
+        for l in input.lines() {
             for n in l.split(",") {
                 positions.push(n.parse::<usize>().unwrap());
             }
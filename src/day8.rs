@@ -1,8 +1,7 @@
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs;
 // use std::intrinsics::assume;
 use crate::day::Day;
-use std::io::{BufRead, BufReader};
 
 struct Display {
     patterns: Vec<Vec<char>>,
@@ -115,14 +114,14 @@ pub struct Day8 {
 
 impl Day8 {
     pub fn load(filename: &str) -> Day8 {
+        Day8::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day8 {
         // println!("Loading.");
         let mut displays: Vec<Display> = Vec::new();
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let l = line.unwrap();
+        for l in input.lines() {
             let parts: Vec<&str> = l.split("|").collect();
             let obs: Vec<&str> = parts[0].trim().split(" ").collect();
             let disp: Vec<&str> = parts[1].trim().split(" ").collect();
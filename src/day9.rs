@@ -1,8 +1,7 @@
 use std::collections::{HashMap, VecDeque};
-use std::fs::File;
+use std::fs;
 // use std::intrinsics::assume;
 use crate::day::Day;
-use std::io::{BufRead, BufReader};
 
 pub struct Day9 {
     height_map: HashMap<(usize, usize), usize>,
@@ -10,16 +9,16 @@ pub struct Day9 {
 
 impl Day9 {
     pub fn load(filename: &str) -> Day9 {
+        Day9::load_str(&fs::read_to_string(filename).unwrap())
+    }
+
+    pub fn load_str(input: &str) -> Day9 {
         // println!("Loading.");
         let mut height_map: HashMap<(usize, usize), usize> = HashMap::new();
 
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
-
         let mut row: usize = 0;
-        for line in reader.lines() {
+        for l in input.lines() {
             row += 1;
-            let l = line.unwrap();
             let mut col: usize = 0;
             for c in l.chars() {
                 col += 1;
@@ -167,6 +166,29 @@ impl Day for Day9 {
 
         Ok(largest[0] * largest[1] * largest[2])
     }
+
+    fn explain(&self) -> Vec<(String, String)> {
+        let mut drains = self.drains();
+        drains.sort_by_key(|&(col, row)| (row, col));
+
+        // Basins as (size, low point), largest first.
+        let mut basins: Vec<(usize, (usize, usize))> =
+            drains.iter().map(|d| (self.basin_size(*d), *d)).collect();
+        basins.sort_by(|a, b| b.cmp(a));
+
+        // Coordinates are reported 0-based, (column, row).
+        let low_points: Vec<String> = drains.iter()
+            .map(|(col, row)| format!("{},{}", col - 1, row - 1))
+            .collect();
+
+        let mut details = vec![(String::from("low points"), low_points.join(" "))];
+        for (n, (size, (col, row))) in basins.iter().take(3).enumerate() {
+            details.push((format!("basin {}", n + 1),
+                          format!("size {} at low point {},{}", size, col - 1, row - 1)));
+        }
+
+        details
+    }
 }
 
 #[cfg(test)]
@@ -186,6 +208,15 @@ mod tests {
         assert_eq!(d.sum_risks(), 15);
     }
 
+    #[test]
+    fn test_explain() {
+        let d = Day9::load("data/day9_example1.txt");
+        let details = d.explain();
+        assert_eq!(details[0], (String::from("low points"), String::from("1,0 9,0 2,2 6,4")));
+        assert_eq!(details[1], (String::from("basin 1"), String::from("size 14 at low point 2,2")));
+        assert_eq!(details.len(), 4);
+    }
+
     #[test]
     // Test results based on my inputs.  Yours will be different.
     fn test_part1() {
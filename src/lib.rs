@@ -0,0 +1,100 @@
+#[macro_use]
+extern crate lazy_static;
+extern crate regex;
+
+pub mod day;
+// mod day_n;
+pub mod day1;
+pub mod day2;
+pub mod day3;
+pub mod day4;
+pub mod day5;
+pub mod day6;
+pub mod day7;
+pub mod day8;
+pub mod day9;
+pub mod day10;
+pub mod day11;
+pub mod day12;
+pub mod day13;
+pub mod day14;
+pub mod day15;
+pub mod day16;
+pub mod day17;
+pub mod day18;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "python")]
+mod python;
+pub mod bingo;
+pub mod rng;
+pub mod watch;
+
+use day::{Day, Param};
+use std::fs;
+use std::path::Path;
+
+// Number of days implemented so far.
+pub const DAYS: usize = 18;
+
+// Builds day n from its puzzle input.  Returns None if there's no such day.
+pub fn load_day(n: usize, input: &str) -> Option<Box<dyn Day>> {
+    let day: Box<dyn Day> = match n {
+        1 => Box::new(day1::Day1::load_str(input)),
+        2 => Box::new(day2::Day2::load_str(input)),
+        3 => Box::new(day3::Day3::load_str(input)),
+        4 => Box::new(day4::Day4::load_str(input)),
+        5 => Box::new(day5::Day5::load_str(input)),
+        6 => Box::new(day6::Day6::load_str(input)),
+        7 => Box::new(day7::Day7::load_str(input)),
+        8 => Box::new(day8::Day8::load_str(input)),
+        9 => Box::new(day9::Day9::load_str(input)),
+        10 => Box::new(day10::Day10::load_str(input)),
+        11 => Box::new(day11::Day11::load_str(input)),
+        12 => Box::new(day12::Day12::load_str(input)),
+        13 => Box::new(day13::Day13::load_str(input)),
+        14 => Box::new(day14::Day14::load_str(input)),
+        15 => Box::new(day15::Day15::load_str(input)),
+        16 => Box::new(day16::Day16::load_str(input)),
+        17 => Box::new(day17::Day17::load_str(input)),
+        18 => Box::new(day18::Day18::load_str(input)),
+        _ => return None,
+    };
+
+    Some(day)
+}
+
+// Parameters day n understands, the same as its params() but without loading it.
+pub fn day_params(n: usize) -> &'static [Param] {
+    match n {
+        1 => day1::PARAMS,
+        5 => day5::PARAMS,
+        6 => day6::PARAMS,
+        11 => day11::PARAMS,
+        14 => day14::PARAMS,
+        15 => day15::PARAMS,
+        _ => &[],
+    }
+}
+
+// Builds day n from an input file.  Like load_day(), but day 2 course scripts resolve their
+// #include lines relative to the file.
+pub fn load_day_file(n: usize, path: &Path) -> Option<Box<dyn Day>> {
+    match n {
+        2 => Some(Box::new(day2::Day2::load(&path.to_string_lossy()))),
+        _ => load_day(n, &fs::read_to_string(path).unwrap()),
+    }
+}
+
+// Applies the overrides in params that this day declares, skipping the rest.
+pub fn apply_params(day: &mut dyn Day, params: &[(String, usize)]) -> Result<(), String> {
+    let declared = day.params();
+    for (name, value) in params {
+        if declared.iter().any(|p| p.name == name) {
+            day.set_param(name, *value)?;
+        }
+    }
+
+    Ok(())
+}
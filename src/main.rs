@@ -1,49 +1,15 @@
-#[macro_use]
-extern crate lazy_static;
-extern crate regex;
-
-mod day;
-// mod day_n;
-mod day1;
-mod day2;
-mod day3;
-mod day4;
-mod day5;
-mod day6;
-mod day7;
-mod day8;
-mod day9;
-mod day10;
-mod day11;
-mod day12;
-mod day13;
-mod day14;
-mod day15;
-mod day16;
-mod day17;
-mod day18;
-
-use day::Day;
-use day1::Day1;
-use day2::Day2;
-use day3::Day3;
-use day4::Day4;
-use day5::Day5;
-use day6::Day6;
-use day7::Day7;
-use day8::Day8;
-use day9::Day9;
-use day10::Day10;
-use day11::Day11;
-use day12::Day12;
-use day13::Day13;
-use day14::Day14;
-use day15::Day15;
-use day16::Day16;
-use day17::Day17;
-use day18::Day18;
-
-fn do_day(n: usize, day: &dyn Day) {
+use rs_aoc2021::bingo::{self, Client, ServerConfig};
+use rs_aoc2021::day::Day;
+use rs_aoc2021::day1::{Day1, FilterPipeline};
+use rs_aoc2021::day2::{self, Day2};
+use rs_aoc2021::day4::{Day4, Finish, Rigged, WinRules};
+use rs_aoc2021::day5::{Day5, ImageFormat};
+use std::env;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+fn do_day(n: usize, day: &dyn Day, explain: bool) {
     match day.part1() {
         Ok(val) => println!("day {}, part 1: {}", n, val),
         Err(msg) => println!("day {}, part 1: {}", n, msg),
@@ -52,51 +18,440 @@ fn do_day(n: usize, day: &dyn Day) {
         Ok(val) => println!("day {}, part 2: {}", n, val),
         Err(msg) => println!("day {}, part 2: {}", n, msg),
     }
+    if explain {
+        for (name, value) in day.explain() {
+            println!("    {}: {}", name, value);
+        }
+    }
+}
+
+const USAGE: &str = "\
+usage: rs_aoc2021 [DAY] [--explain] [--param NAME=VALUE]...
+       rs_aoc2021 DAY --watch [--param NAME=VALUE]...
+       rs_aoc2021 [DAY] --list-params
+       rs_aoc2021 1 --report text|json
+       rs_aoc2021 1 --filter mean:W|median:W|hysteresis:D|outlier:W:L[,...] [--explain] \
+[--param NAME=VALUE]...
+       rs_aoc2021 2 --trajectory csv|svg [--model direct|aim|lateral]
+       rs_aoc2021 4 --odds TRIALS [--seed N]
+       rs_aoc2021 4 --timeline text|json
+       rs_aoc2021 4 --rig CARD first|last
+       rs_aoc2021 4 --serve ADDR [--players N] [--interval MS]
+       rs_aoc2021 4 --join ADDR NAME
+       rs_aoc2021 5 --heatmap pgm|ppm|svg [--param NAME=VALUE]...";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn load(n: usize) -> Box<dyn Day> {
+    let filename = format!("data/day{}_input.txt", n);
+    rs_aoc2021::load_day_file(n, Path::new(&filename)).unwrap()
+}
+
+// Parses NAME=VALUE from --param.
+fn parse_param(arg: &str) -> Option<(String, usize)> {
+    let mut parts = arg.splitn(2, '=');
+    let name = parts.next()?.trim();
+    let value = parts.next()?.trim().parse::<usize>().ok()?;
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name.to_string(), value))
+}
+
+fn list_params(n: usize) {
+    let show = |v: Option<usize>| v.map(|v| v.to_string()).unwrap_or_else(|| String::from("-"));
+    for param in rs_aoc2021::day_params(n) {
+        println!("day {}: {} (part 1: {}, part 2: {})  {}",
+                 n, param.name, show(param.defaults.0), show(param.defaults.1), param.help);
+    }
+}
+
+// What the command line asks for, one per line of the usage.
+enum Mode {
+    // Solve the selected day, or all of them.
+    Solve,
+    Watch,
+    ListParams,
+    Report(String),
+    Filter(FilterPipeline),
+    Trajectory(String),
+    Odds(usize),
+    Timeline(String),
+    Rig(usize, Finish),
+    Serve(String),
+    Join(String, String),
+    Heatmap(ImageFormat),
+}
+
+impl Mode {
+    // The one day the mode works on, if it's tied to one.
+    fn day(&self) -> Option<usize> {
+        match self {
+            Mode::Solve | Mode::Watch | Mode::ListParams => None,
+            Mode::Report(_) | Mode::Filter(_) => Some(1),
+            Mode::Trajectory(_) => Some(2),
+            Mode::Odds(_) | Mode::Timeline(_) | Mode::Rig(..) | Mode::Serve(_)
+                | Mode::Join(..) => Some(4),
+            Mode::Heatmap(_) => Some(5),
+        }
+    }
+
+    // Whether an option flag means anything in this mode.
+    fn takes(&self, flag: &str) -> bool {
+        match self {
+            Mode::Solve | Mode::Filter(_) => flag == "--explain" || flag == "--param",
+            Mode::Watch | Mode::Heatmap(_) => flag == "--param",
+            Mode::Trajectory(_) => flag == "--model",
+            Mode::Odds(_) => flag == "--seed",
+            Mode::Serve(_) => flag == "--players" || flag == "--interval",
+            _ => false,
+        }
+    }
+}
+
+struct Options {
+    mode: Mode,
+    day: Option<usize>,
+    // Days the mode runs, for checking --param names against.
+    days: Vec<usize>,
+    explain: bool,
+    params: Vec<(String, usize)>,
+    model: String,
+    seed: u64,
+    server: ServerConfig,
+}
+
+// Reads the command line into a mode and its options, failing with what's wrong with it.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut mode: Option<(String, Mode)> = None;
+    let mut flags: Vec<String> = Vec::new();
+    let mut options = Options {
+        mode: Mode::Solve,
+        day: None,
+        days: Vec::new(),
+        explain: false,
+        params: Vec::new(),
+        model: String::from("aim"),
+        seed: 2021,
+        server: ServerConfig {
+            players: 2,
+            interval: Duration::from_secs(3),
+            join_timeout: Duration::from_secs(60),
+        },
+    };
+    let bad_value = |flag: &str| format!("bad or missing value for {}", flag);
+    while let Some(arg) = args.next() {
+        let picked = match arg.as_str() {
+            "--watch" => Some(Mode::Watch),
+            "--list-params" => Some(Mode::ListParams),
+            "--report" => match args.next() {
+                Some(format) if format == "text" || format == "json" => Some(Mode::Report(format)),
+                _ => return Err(bad_value(&arg)),
+            },
+            "--filter" => match args.next().map(|spec| FilterPipeline::parse(&spec)) {
+                Some(pipeline) => Some(Mode::Filter(pipeline?)),
+                None => return Err(bad_value(&arg)),
+            },
+            "--trajectory" => match args.next() {
+                Some(format) if format == "csv" || format == "svg" => {
+                    Some(Mode::Trajectory(format))
+                }
+                _ => return Err(bad_value(&arg)),
+            },
+            "--odds" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(trials) => Some(Mode::Odds(trials)),
+                None => return Err(bad_value(&arg)),
+            },
+            "--timeline" => match args.next() {
+                Some(format) if format == "text" || format == "json" => {
+                    Some(Mode::Timeline(format))
+                }
+                _ => return Err(bad_value(&arg)),
+            },
+            "--rig" => {
+                let card = args.next().and_then(|n| n.parse::<usize>().ok());
+                let finish = match args.next().as_deref() {
+                    Some("first") => Some(Finish::First),
+                    Some("last") => Some(Finish::Last),
+                    _ => None,
+                };
+                match (card, finish) {
+                    (Some(card), Some(finish)) if card > 0 => Some(Mode::Rig(card, finish)),
+                    _ => return Err(bad_value(&arg)),
+                }
+            }
+            "--serve" => match args.next() {
+                Some(addr) => Some(Mode::Serve(addr)),
+                None => return Err(bad_value(&arg)),
+            },
+            "--join" => match (args.next(), args.next()) {
+                (Some(addr), Some(name)) => Some(Mode::Join(addr, name)),
+                _ => return Err(bad_value(&arg)),
+            },
+            "--heatmap" => match args.next().as_deref() {
+                Some("pgm") => Some(Mode::Heatmap(ImageFormat::Pgm)),
+                Some("ppm") => Some(Mode::Heatmap(ImageFormat::Ppm)),
+                Some("svg") => Some(Mode::Heatmap(ImageFormat::Svg)),
+                _ => return Err(bad_value(&arg)),
+            },
+            "--explain" => {
+                options.explain = true;
+                None
+            }
+            "--param" => match args.next().as_deref().and_then(parse_param) {
+                Some(param) => {
+                    options.params.push(param);
+                    None
+                }
+                None => return Err(bad_value(&arg)),
+            },
+            "--model" => match args.next() {
+                Some(name) => {
+                    options.model = name;
+                    None
+                }
+                None => return Err(bad_value(&arg)),
+            },
+            "--seed" => match args.next().and_then(|n| n.parse::<u64>().ok()) {
+                Some(n) => {
+                    options.seed = n;
+                    None
+                }
+                None => return Err(bad_value(&arg)),
+            },
+            "--players" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => {
+                    options.server.players = n;
+                    None
+                }
+                _ => return Err(bad_value(&arg)),
+            },
+            "--interval" => match args.next().and_then(|n| n.parse::<u64>().ok()) {
+                Some(ms) => {
+                    options.server.interval = Duration::from_millis(ms);
+                    None
+                }
+                None => return Err(bad_value(&arg)),
+            },
+            _ => match arg.parse::<usize>() {
+                Ok(n) if (1..=rs_aoc2021::DAYS).contains(&n) && options.day.is_none() => {
+                    options.day = Some(n);
+                    continue;
+                }
+                _ => return Err(format!("unexpected argument: {}", arg)),
+            },
+        };
+        match (picked, &mode) {
+            (Some(_), Some((other, _))) => {
+                return Err(format!("{} and {} can't be used together", other, arg));
+            }
+            (Some(picked), None) => mode = Some((arg, picked)),
+            (None, _) => flags.push(arg),
+        }
+    }
+
+    let (name, mode) = mode.unwrap_or_else(|| (String::from("solving"), Mode::Solve));
+    if let Some(flag) = flags.iter().find(|flag| !mode.takes(flag)) {
+        return Err(format!("{} doesn't apply to {}", flag, name));
+    }
+    options.days = match (mode.day(), options.day) {
+        (Some(n), Some(day)) if n == day => vec![n],
+        (Some(n), _) => return Err(format!("{} is for day {}", name, n)),
+        (None, Some(day)) => vec![day],
+        (None, None) if matches!(mode, Mode::Watch) => {
+            return Err(String::from("--watch needs a day"));
+        }
+        (None, None) => (1..=rs_aoc2021::DAYS).collect(),
+    };
+
+    // Every override has to mean something to at least one of the days being run.
+    for (name, _) in &options.params {
+        let declared = |n: &usize| rs_aoc2021::day_params(*n).iter().any(|p| p.name == name);
+        if !options.days.iter().any(declared) {
+            return Err(format!("unknown parameter: {}", name));
+        }
+    }
+    options.mode = mode;
+
+    Ok(options)
+}
+
+// Applies --param overrides to a day, exiting if it refuses one.
+fn set_params(n: usize, day: &mut dyn Day, params: &[(String, usize)]) {
+    if let Err(msg) = rs_aoc2021::apply_params(day, params) {
+        eprintln!("day {}: {}", n, msg);
+        process::exit(2);
+    }
 }
 
 fn main() {
+    if env::args().nth(1).as_deref() == Some("--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("rs_aoc2021: {}", msg);
+            usage();
+        }
+    };
+    let params = &options.params;
+
+    match options.mode {
+        Mode::Solve => {
+            banner();
+            let mut days: Vec<(usize, Box<dyn Day>)> =
+                options.days.iter().map(|n| (*n, load(*n))).collect();
+            for (n, day) in days.iter_mut() {
+                set_params(*n, day.as_mut(), params);
+            }
+            for (n, day) in &days {
+                do_day(*n, day.as_ref(), options.explain);
+            }
+        }
+        Mode::Watch => {
+            // Re-solve the selected day whenever its input or example files change.
+            banner();
+            let n = options.days[0];
+            rs_aoc2021::watch::watch(n, Path::new("data"), Duration::from_millis(500), params);
+        }
+        Mode::ListParams => {
+            banner();
+            for n in &options.days {
+                list_params(*n);
+            }
+        }
+        Mode::Report(format) => {
+            // Depth profile of the sonar sweep.  No banner, so JSON stays parseable.
+            let windows: Vec<usize> = (1..=10).collect();
+            let report = Day1::load("data/day1_input.txt").report(&windows, 10);
+            match format.as_str() {
+                "json" => println!("{}", report.to_json()),
+                _ => print!("{}", report),
+            }
+        }
+        Mode::Filter(filters) => {
+            // Day 1 with sonar noise filters on the depths.
+            banner();
+            let mut day = Day1::load("data/day1_input.txt");
+            day.set_filters(filters);
+            set_params(1, &mut day, params);
+            do_day(1, &day, options.explain);
+        }
+        Mode::Trajectory(format) => {
+            // Where the day 2 course went, under the chosen navigation model.  No banner, the
+            // output is meant to be redirected to a file.
+            let result = day2::model_by_name(&options.model)
+                .and_then(|model| Day2::load("data/day2_input.txt").trajectory(model.as_ref())
+                                       .map_err(|e| e.to_string()));
+            match result {
+                Ok(t) if format == "svg" => print!("{}", t.to_svg()),
+                Ok(t) => print!("{}", t.to_csv()),
+                Err(msg) => {
+                    eprintln!("day 2: {}", msg);
+                    process::exit(1);
+                }
+            }
+        }
+        Mode::Odds(trials) => {
+            // Each card's chances over random call orders.
+            print!("{}", Day4::load("data/day4_input.txt").odds(trials, options.seed));
+        }
+        Mode::Timeline(format) => {
+            // Every call of the bingo game and the final ranking.
+            let timeline = Day4::load("data/day4_input.txt").timeline();
+            match format.as_str() {
+                "json" => println!("{}", timeline.to_json()),
+                _ => print!("{}", timeline),
+            }
+        }
+        Mode::Rig(card, finish) => rig(card, finish),
+        Mode::Serve(addr) => serve(&addr, options.server),
+        Mode::Join(addr, name) => {
+            // Plays in a bingo game automatically, printing what the server says.
+            let result = Client::join(&addr, &name, &WinRules::default()).and_then(|client| {
+                let numbers: Vec<String> =
+                    client.card().numbers().iter().map(|n| n.to_string()).collect();
+                println!("card: {}", numbers.join(" "));
+                client.play_live(|message| println!("{}", message))
+            });
+            if let Err(e) = result {
+                eprintln!("day 4: {}", e);
+                process::exit(1);
+            }
+        }
+        Mode::Heatmap(format) => {
+            // The part 2 vent map as an image, meant to be redirected to a file.
+            let mut d = Day5::load("data/day5_input.txt");
+            set_params(5, &mut d, params);
+            match d.heatmap(true, format) {
+                Ok(image) => print!("{}", image),
+                Err(e) => {
+                    eprintln!("day 5: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn banner() {
     println!("Advent of Code 2021!");
     println!("See adventofcode.com/2021 for background.");
-    println!("");
-
-    let day1 = Day1::load("data/day1_input.txt");
-    let day2 = Day2::load("data/day2_input.txt");
-    let day3 = Day3::load("data/day3_input.txt");
-    let day4 = Day4::load("data/day4_input.txt");
-    let day5 = Day5::load("data/day5_input.txt");
-    let day6 = Day6::load("data/day6_input.txt");
-    let day7 = Day7::load("data/day7_input.txt");
-    let day8 = Day8::load("data/day8_input.txt");
-    let day9 = Day9::load("data/day9_input.txt");
-    let day10 = Day10::load("data/day10_input.txt");
-    let day11 = Day11::load("data/day11_input.txt");
-    let day12 = Day12::load("data/day12_input.txt");
-    let day13 = Day13::load("data/day13_input.txt");
-    let day14 = Day14::load("data/day14_input.txt");
-    let day15 = Day15::load("data/day15_input.txt");
-    let day16 = Day16::load("data/day16_input.txt");
-    let day17 = Day17::load("data/day17_input.txt");
-    let day18 = Day18::load("data/day18_input.txt");
-    let days: Vec<&dyn Day> = vec![
-        &day1, &day2, &day3, &day4, &day5, &day6, &day7, &day8, &day9, &day10,
-        &day11, &day12,
-        &day13, &day14,
-        &day15,
-        &day16,
-        &day17,
-        &day18,
-    ];
-
-    let selected_day: Option<usize> = None;
-    match selected_day {
-        None => {
-            // No day selected, do them all
-            for (n, day) in days.iter().enumerate() {
-                do_day(n + 1, *day);
+    println!();
+}
+
+// Prints the shortest call order that makes a card (numbered from 1) win first or last, as
+// the calls line of a day 4 input.
+fn rig(card: usize, finish: Finish) {
+    let d = Day4::load("data/day4_input.txt");
+    if card > d.cards().len() {
+        eprintln!("day 4: there are only {} cards", d.cards().len());
+        process::exit(2);
+    }
+    let end = if finish == Finish::First { "first" } else { "last" };
+    let calls = match d.rig(card - 1, finish) {
+        Rigged::Shortest(calls) => calls,
+        Rigged::Unfinished(Some(calls)) => {
+            eprintln!("day 4: search limit reached, there may be a shorter order");
+            calls
+        }
+        Rigged::Unfinished(None) => {
+            eprintln!("day 4: search limit reached without making card {} win {}", card, end);
+            process::exit(1);
+        }
+        Rigged::Impossible => {
+            eprintln!("day 4: no call order makes card {} win {}", card, end);
+            process::exit(1);
+        }
+    };
+    let calls: Vec<String> = calls.iter().map(|n| n.to_string()).collect();
+    println!("{}", calls.join(","));
+}
+
+// A live bingo game with the day 4 calls and cards, see src/bingo.rs for the protocol.
+fn serve(addr: &str, config: ServerConfig) {
+    let players = config.players;
+    let result = bingo::Server::bind(addr, Day4::load("data/day4_input.txt"), config)
+        .and_then(|server| {
+            println!("bingo on {}, waiting for {} players", server.local_addr()?, players);
+            server.run()
+        });
+    match result {
+        Ok(winners) => {
+            for w in winners {
+                println!("{} wins with card {} in round {}, score {}",
+                         w.player, w.card + 1, w.round, w.score);
             }
         }
-        Some(n) => {
-            do_day(n - 1, days[n - 1]);
+        Err(e) => {
+            eprintln!("day 4: {}", e);
+            process::exit(1);
         }
     }
 }
@@ -104,6 +459,61 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rs_aoc2021::day3::Day3;
+    use rs_aoc2021::day6::Day6;
+    use rs_aoc2021::day7::Day7;
+    use rs_aoc2021::day8::Day8;
+    use rs_aoc2021::day9::Day9;
+    use rs_aoc2021::day10::Day10;
+    use rs_aoc2021::day11::Day11;
+    use rs_aoc2021::day12::Day12;
+    use rs_aoc2021::day13::Day13;
+    use rs_aoc2021::day14::Day14;
+    use rs_aoc2021::day15::Day15;
+    use rs_aoc2021::day16::Day16;
+    use rs_aoc2021::day17::Day17;
+    use rs_aoc2021::day18::Day18;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse("").unwrap();
+        assert!(matches!(options.mode, Mode::Solve));
+        assert_eq!(options.days, (1..=rs_aoc2021::DAYS).collect::<Vec<usize>>());
+        let options = parse("2 --model direct --trajectory svg").unwrap();
+        assert!(matches!(options.mode, Mode::Trajectory(ref format) if format == "svg"));
+        assert_eq!(options.model, "direct");
+        let options = parse("--param window=3 1 --filter median:3 --explain").unwrap();
+        assert!(matches!(options.mode, Mode::Filter(_)));
+        assert!(options.explain);
+
+        // Options for another mode, or no mode.
+        assert!(parse("2 --model direct").is_err());
+        assert!(parse("4 --timeline text --seed 3").is_err());
+        assert!(parse("4 --odds 10 --interval 100").is_err());
+        assert!(parse("5 --heatmap pgm --explain").is_err());
+        // Modes for another day, two modes, or a missing day.
+        assert!(parse("--heatmap svg").is_err());
+        assert!(parse("1 --heatmap svg").is_err());
+        assert!(parse("4 --odds 10 --timeline json").is_err());
+        assert!(parse("--watch").is_err());
+        assert!(parse("1 2").is_err());
+        // Parameters the days being run don't have, with filters too.
+        assert!(parse("6 --param window=3").is_err());
+        assert!(parse("1 --filter mean:2 --param generations=3").is_err());
+        assert!(parse("--param generations=3").is_ok());
+    }
+
+    #[test]
+    fn test_parse_param() {
+        assert_eq!(parse_param("generations=1000"), Some((String::from("generations"), 1000)));
+        assert_eq!(parse_param("generations"), None);
+        assert_eq!(parse_param("=3"), None);
+        assert_eq!(parse_param("window=-1"), None);
+    }
 
     #[test]
     // Test results based on my inputs.  Yours will be different.
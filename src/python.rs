@@ -0,0 +1,183 @@
+// Python bindings for a few of the puzzle models.  Build with
+// `cargo build --release --features python` and copy the resulting shared library
+// to `rs_aoc2021.so` (`rs_aoc2021.pyd` on Windows) somewhere on the Python path.
+//
+//     import rs_aoc2021
+//     d = rs_aoc2021.Day4(open("data/day4_input.txt").read())
+//     d.play(0)    # -> (round, score) or None
+
+// The wrappers generated by #[pymethods] trip this lint on every PyResult return.
+#![allow(clippy::useless_conversion)]
+
+use crate::day::Day;
+use crate::day15::{Day15, Solver};
+use crate::day16::Day16;
+use crate::day18::{Day18, Num};
+use crate::day4::{Day4, WinRules};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+
+// Errors from the Rust side become ValueErrors, never panics.
+fn to_py<T, E: ToString>(result: Result<T, E>) -> PyResult<T> {
+    result.map_err(|msg| PyValueError::new_err(msg.to_string()))
+}
+
+#[pyclass(name = "Day4")]
+struct PyDay4 {
+    inner: Day4,
+}
+
+#[pymethods]
+impl PyDay4 {
+    #[new]
+    fn new(input: &str) -> PyResult<PyDay4> {
+        Ok(PyDay4 { inner: to_py(Day4::parse(input, &WinRules::default()))? })
+    }
+
+    fn part1(&self) -> PyResult<usize> {
+        to_py(self.inner.part1())
+    }
+
+    fn part2(&self) -> PyResult<usize> {
+        to_py(self.inner.part2())
+    }
+
+    #[getter]
+    fn calls(&self) -> Vec<usize> {
+        self.inner.calls.clone()
+    }
+
+    #[getter]
+    fn num_cards(&self) -> usize {
+        self.inner.cards.len()
+    }
+
+    // Plays one card against the loaded calls (or the given calls).
+    // Returns (round, score) if the card wins, otherwise None.
+    #[pyo3(signature = (card, calls = None))]
+    fn play(&self, card: usize, calls: Option<Vec<usize>>) -> PyResult<Option<(usize, usize)>> {
+        let card = self.inner.cards.get(card)
+            .ok_or_else(|| PyIndexError::new_err("no such card"))?;
+        Ok(card.play(calls.as_ref().unwrap_or(&self.inner.calls)))
+    }
+}
+
+#[pyclass(name = "Day15")]
+struct PyDay15 {
+    inner: Day15,
+}
+
+#[pymethods]
+impl PyDay15 {
+    #[new]
+    fn new(input: &str) -> PyResult<PyDay15> {
+        Ok(PyDay15 { inner: to_py(Day15::parse(input))? })
+    }
+
+    fn part1(&self) -> PyResult<usize> {
+        to_py(self.inner.part1())
+    }
+
+    fn part2(&self) -> PyResult<usize> {
+        to_py(self.inner.part2())
+    }
+
+    // Total risk of the safest path across the map tiled tiles x tiles times.
+    #[pyo3(signature = (tiles = 1))]
+    fn lowest_risk(&self, tiles: usize) -> PyResult<usize> {
+        if tiles == 0 {
+            return Err(PyValueError::new_err("tiles must be at least 1"));
+        }
+        Ok(Solver::new_augmented(&self.inner, tiles).run())
+    }
+}
+
+#[pyclass(name = "Day16")]
+struct PyDay16 {
+    inner: Day16,
+}
+
+#[pymethods]
+impl PyDay16 {
+    #[new]
+    fn new(input: &str) -> PyResult<PyDay16> {
+        Ok(PyDay16 { inner: to_py(Day16::parse(input))? })
+    }
+
+    fn part1(&self) -> PyResult<usize> {
+        to_py(self.inner.part1())
+    }
+
+    fn part2(&self) -> PyResult<usize> {
+        to_py(self.inner.part2())
+    }
+
+    fn to_bits(&self) -> Vec<bool> {
+        self.inner.to_bits()
+    }
+
+    // Decodes the outermost packet, returns (value, bits consumed, sum of versions)
+    fn decode(&self) -> PyResult<(isize, usize, usize)> {
+        let bits = self.inner.to_bits();
+        to_py(self.inner.scan_packet(&bits))
+    }
+}
+
+#[pyclass(name = "Day18")]
+struct PyDay18 {
+    inner: Day18,
+}
+
+#[pymethods]
+impl PyDay18 {
+    #[new]
+    fn new(input: &str) -> PyResult<PyDay18> {
+        Ok(PyDay18 { inner: to_py(Day18::parse(input))? })
+    }
+
+    fn part1(&self) -> PyResult<usize> {
+        to_py(self.inner.part1())
+    }
+
+    fn part2(&self) -> PyResult<usize> {
+        to_py(self.inner.part2())
+    }
+
+    #[getter]
+    fn numbers(&self) -> Vec<String> {
+        self.inner.numbers.iter().map(|n| n.to_string()).collect()
+    }
+
+    // Reduced sum of all loaded numbers, in puzzle notation.
+    fn sum(&self) -> String {
+        let mut sum = Num::zero();
+        for n in self.inner.numbers.iter() {
+            sum.add(n);
+        }
+        sum.to_string()
+    }
+}
+
+// Adds two snailfish numbers, returns the reduced result in puzzle notation.
+#[pyfunction]
+fn snailfish_add(a: &str, b: &str) -> PyResult<String> {
+    let mut sum = to_py(Num::try_parse(a))?;
+    sum.add(&to_py(Num::try_parse(b))?);
+    Ok(sum.to_string())
+}
+
+#[pyfunction]
+fn snailfish_magnitude(a: &str) -> PyResult<usize> {
+    Ok(to_py(Num::try_parse(a))?.magnitude())
+}
+
+#[pymodule]
+fn rs_aoc2021(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDay4>()?;
+    m.add_class::<PyDay15>()?;
+    m.add_class::<PyDay16>()?;
+    m.add_class::<PyDay18>()?;
+    m.add_function(wrap_pyfunction!(snailfish_add, m)?)?;
+    m.add_function(wrap_pyfunction!(snailfish_magnitude, m)?)?;
+    Ok(())
+}
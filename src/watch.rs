@@ -0,0 +1,194 @@
+// Watch mode: re-solve a day whenever its input or example files change.
+//
+// Files are polled for modification time rather than using OS notifications, so this works
+// the same everywhere, including on network mounts.
+
+use crate::load_day_file;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// Answers for one file, errors (including loader panics) kept as text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Answers {
+    pub part1: Result<usize, String>,
+    pub part2: Result<usize, String>,
+}
+
+// Input and example files for day n in data_dir, e.g. day4_input.txt, day4_example1.txt.
+pub fn watched_files(n: usize, data_dir: &Path) -> Vec<PathBuf> {
+    let input = format!("day{}_input.txt", n);
+    let example = format!("day{}_example", n);
+
+    let mut files = Vec::new();
+    if let Ok(entries) = fs::read_dir(data_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == input || (name.starts_with(&example) && name.ends_with(".txt")) {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+
+    files
+}
+
+thread_local! {
+    // Set while solve_file() runs a day, whose panics it reports itself.
+    static SOLVING: Cell<bool> = const { Cell::new(false) };
+}
+
+// Keeps panics inside solve_file() off stderr, passing any others on to the hook that was
+// there before.
+fn quiet_solver_panics() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !SOLVING.with(|solving| solving.get()) {
+                previous(info);
+            }
+        }));
+    });
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("panic")
+    }
+}
+
+// Loads day n from filename and runs both parts.  A half-edited file usually makes the
+// loader panic, that's reported as an error instead of ending the watch.
+pub fn solve_file(n: usize, filename: &Path, params: &[(String, usize)]) -> Answers {
+    if let Err(e) = fs::metadata(filename) {
+        let msg = format!("can't read {}: {}", filename.display(), e);
+        return Answers { part1: Err(msg.clone()), part2: Err(msg) };
+    }
+
+    quiet_solver_panics();
+    SOLVING.with(|solving| solving.set(true));
+    let result = panic::catch_unwind(|| {
+        let mut day = load_day_file(n, filename).expect("no such day");
+        for (name, value) in params {
+            if let Err(msg) = day.set_param(name, *value) {
+                return Answers { part1: Err(msg.clone()), part2: Err(msg) };
+            }
+        }
+        let part1 = day.part1().map_err(|e| e.to_string());
+        let part2 = day.part2().map_err(|e| e.to_string());
+        Answers { part1, part2 }
+    });
+    SOLVING.with(|solving| solving.set(false));
+
+    match result {
+        Ok(answers) => answers,
+        Err(payload) => {
+            let msg = format!("failed: {}", panic_message(payload));
+            Answers { part1: Err(msg.clone()), part2: Err(msg) }
+        }
+    }
+}
+
+fn show(answer: &Result<usize, String>) -> String {
+    match answer {
+        Ok(val) => val.to_string(),
+        Err(msg) => msg.clone(),
+    }
+}
+
+// One line per part comparing new answers against the previous ones, if any.
+pub fn diff(previous: Option<&Answers>, current: &Answers) -> Vec<String> {
+    let parts = [
+        (1, previous.map(|p| &p.part1), &current.part1),
+        (2, previous.map(|p| &p.part2), &current.part2),
+    ];
+
+    let mut lines = Vec::new();
+    for (part, before, after) in parts.iter() {
+        let line = match before {
+            None => format!("part {}: {}", part, show(after)),
+            Some(before) if before == after => format!("part {}: {} (unchanged)", part, show(after)),
+            Some(before) => format!("part {}: {} (was {})", part, show(after), show(before)),
+        };
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Solves every watched file once, then again each time one changes.  Never returns.
+pub fn watch(n: usize, data_dir: &Path, interval: Duration, params: &[(String, usize)]) {
+    let mut seen: HashMap<PathBuf, Option<SystemTime>> = HashMap::new();
+    let mut answers: HashMap<PathBuf, Answers> = HashMap::new();
+
+    println!("Watching day {} files in {} (Ctrl-C to stop)", n, data_dir.display());
+    loop {
+        for file in watched_files(n, data_dir) {
+            let stamp = modified(&file);
+            if seen.get(&file) == Some(&stamp) {
+                continue;
+            }
+            seen.insert(file.clone(), stamp);
+
+            let current = solve_file(n, &file, params);
+            println!("{}:", file.display());
+            for line in diff(answers.get(&file), &current) {
+                println!("  {}", line);
+            }
+            answers.insert(file, current);
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let before = Answers { part1: Ok(7), part2: Ok(5) };
+        let after = Answers { part1: Ok(7), part2: Err(String::from("oops")) };
+
+        assert_eq!(diff(None, &before), vec!["part 1: 7", "part 2: 5"]);
+        assert_eq!(diff(Some(&before), &after),
+                   vec!["part 1: 7 (unchanged)", "part 2: oops (was 5)"]);
+    }
+
+    #[test]
+    fn test_solve_file() {
+        let dir = std::env::temp_dir().join(format!("rs_aoc2021_watch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let example = dir.join("day1_example1.txt");
+        fs::write(&example, "199\n200\n208\n210\n200\n207\n240\n269\n260\n263\n").unwrap();
+        fs::write(dir.join("day1_notes.txt"), "not watched").unwrap();
+
+        assert_eq!(watched_files(1, &dir), vec![example.clone()]);
+        assert_eq!(solve_file(1, &example, &[]), Answers { part1: Ok(7), part2: Ok(5) });
+        let window = [(String::from("window"), 1)];
+        assert_eq!(solve_file(1, &example, &window), Answers { part1: Ok(7), part2: Ok(7) });
+
+        fs::write(&example, "199\nnot a depth\n").unwrap();
+        assert!(solve_file(1, &example, &[]).part1.is_err());
+        // Only panics while solving are kept quiet.
+        assert!(!SOLVING.with(|solving| solving.get()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+// Builds tests/capi/harness.c against the cdylib and runs it.
+#![cfg(feature = "capi")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Directory holding the cdylib.  Cargo builds it next to the test binary, in target/debug/deps.
+fn lib_dir() -> PathBuf {
+    let mut dir = env::current_exe().unwrap();
+    dir.pop();
+    dir
+}
+
+// Flags to link libpython.  Built with the python feature too (as by --all-features), the
+// library is a Python extension module, which leaves the Py* symbols for the interpreter to
+// provide, so a C program using it has to bring libpython itself.
+#[cfg(unix)]
+fn python_ldflags() -> Vec<String> {
+    let python = env::var("PYTHON").unwrap_or_else(|_| String::from("python3"));
+    let output = Command::new(format!("{}-config", python))
+        .args(["--ldflags", "--embed"])
+        .output()
+        .expect("failed to run python3-config");
+    assert!(output.status.success(), "python3-config --ldflags --embed failed");
+    String::from_utf8_lossy(&output.stdout).split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn test_header_up_to_date() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let generated = fs::read_to_string(env!("CAPI_HEADER")).unwrap();
+    let checked_in = fs::read_to_string(manifest_dir.join("include/rs_aoc2021.h")).unwrap();
+    assert!(generated == checked_in,
+            "include/rs_aoc2021.h is out of date, rebuild with UPDATE_CAPI_HEADER=1");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_c_harness() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = lib_dir();
+    let exe = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("capi_harness");
+    let cc = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+
+    let mut command = Command::new(cc);
+    command.arg(manifest_dir.join("tests/capi/harness.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lrs_aoc2021")
+        .arg("-o")
+        .arg(&exe);
+    if cfg!(feature = "python") {
+        command.args(python_ldflags());
+    }
+    let status = command.status().expect("failed to run the C compiler");
+    assert!(status.success(), "harness failed to compile");
+
+    let output = Command::new(&exe)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .env("DYLD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "harness failed:\n{}{}", stdout, stderr);
+    assert_eq!(stdout.trim(), "ok");
+}
@@ -0,0 +1,90 @@
+/* Exercises the C ABI in include/rs_aoc2021.h.  Built and run by tests/capi.rs. */
+
+#include <stdio.h>
+#include <string.h>
+#include "rs_aoc2021.h"
+
+static int failures = 0;
+
+#define CHECK(cond) \
+    do { \
+        if (!(cond)) { \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            failures++; \
+        } \
+    } while (0)
+
+static const char *DAY1_EXAMPLE = "199\n200\n208\n210\n200\n207\n240\n269\n260\n263\n";
+static const char *DAY2_EXAMPLE = "forward 5\ndown 5\nforward 8\nup 3\ndown 8\nforward 2\n";
+
+static void check_solve(uint32_t day_no, const char *input, uint64_t part1, uint64_t part2) {
+    char *error = NULL;
+    uint64_t answer = 0;
+    AocDay *day = aoc_day_new(day_no, (const uint8_t *)input, strlen(input), &error);
+    CHECK(day != NULL);
+    CHECK(error == NULL);
+    if (day == NULL) {
+        aoc_string_free(error);
+        return;
+    }
+
+    CHECK(aoc_day_solve(day, 1, &answer, &error) == AOC_OK);
+    CHECK(answer == part1);
+    CHECK(aoc_day_solve(day, 2, &answer, &error) == AOC_OK);
+    CHECK(answer == part2);
+    CHECK(error == NULL);
+
+    aoc_day_free(day);
+}
+
+static void check_errors(void) {
+    char *error = NULL;
+    uint64_t answer = 0;
+
+    /* Unknown day */
+    CHECK(aoc_day_new(99, (const uint8_t *)"", 0, &error) == NULL);
+    CHECK(error != NULL && strstr(error, "no such day") != NULL);
+    aoc_string_free(error);
+    error = NULL;
+
+    /* Bad UTF-8 */
+    CHECK(aoc_day_new(1, (const uint8_t *)"\xff\xfe", 2, &error) == NULL);
+    CHECK(error != NULL && strstr(error, "UTF-8") != NULL);
+    aoc_string_free(error);
+    error = NULL;
+
+    /* Unparseable input panics inside the loader, which must not cross the ABI */
+    CHECK(aoc_day_new(1, (const uint8_t *)"abc\n", 4, &error) == NULL);
+    CHECK(error != NULL && strstr(error, "failed to load day 1") != NULL);
+    aoc_string_free(error);
+    error = NULL;
+
+    /* Errors may be ignored by passing NULL */
+    CHECK(aoc_day_new(0, NULL, 0, NULL) == NULL);
+
+    /* Unknown part */
+    AocDay *day = aoc_day_new(1, (const uint8_t *)DAY1_EXAMPLE, strlen(DAY1_EXAMPLE), NULL);
+    CHECK(day != NULL);
+    CHECK(aoc_day_solve(day, 3, &answer, &error) == AOC_ERROR);
+    CHECK(error != NULL && strstr(error, "no such part") != NULL);
+    aoc_string_free(error);
+    error = NULL;
+    CHECK(aoc_day_solve(NULL, 1, &answer, &error) == AOC_ERROR);
+    aoc_string_free(error);
+    aoc_day_free(day);
+
+    /* Freeing NULL is allowed */
+    aoc_day_free(NULL);
+    aoc_string_free(NULL);
+}
+
+int main(void) {
+    check_solve(1, DAY1_EXAMPLE, 7, 5);
+    check_solve(2, DAY2_EXAMPLE, 150, 900);
+    check_errors();
+
+    if (failures == 0) {
+        printf("ok\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
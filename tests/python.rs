@@ -0,0 +1,32 @@
+// Loads the cdylib as the rs_aoc2021 extension module and runs tests/python/test_bindings.py.
+#![cfg(feature = "python")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_python_bindings() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // Cargo builds the cdylib next to the test binary, in target/debug/deps.
+    let mut lib = env::current_exe().unwrap();
+    lib.pop();
+    lib.push("librs_aoc2021.so");
+
+    // Python only imports the module under its own name.
+    let module_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("python");
+    fs::create_dir_all(&module_dir).unwrap();
+    fs::copy(&lib, module_dir.join("rs_aoc2021.so")).unwrap();
+
+    let python = env::var("PYTHON").unwrap_or_else(|_| String::from("python3"));
+    let output = Command::new(python)
+        .arg(manifest_dir.join("tests/python/test_bindings.py"))
+        .env("PYTHONPATH", &module_dir)
+        .output()
+        .expect("failed to run python");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "python tests failed:\n{}{}", stdout, stderr);
+}
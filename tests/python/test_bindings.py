@@ -0,0 +1,117 @@
+"""Exercises the pyo3 bindings in src/python.rs.  Run by tests/python.rs."""
+
+import unittest
+
+import rs_aoc2021
+
+DAY4_EXAMPLE = """7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
+
+22 13 17 11  0
+ 8  2 23  4 24
+21  9 14 16  7
+ 6 10  3 18  5
+ 1 12 20 15 19
+
+ 3 15  0  2 22
+ 9 18 13 17  5
+19  8  7 25 23
+20 11 10 24  4
+14 21 16 12  6
+
+14 21 17 24  4
+10 16 15  9 19
+18  8 23 26 20
+22 11 13  6  5
+ 2  0 12  3  7
+"""
+
+DAY15_EXAMPLE = """1163751742
+1381373672
+2136511328
+3694931569
+7463417111
+1319128137
+1359912421
+3125421639
+1293138521
+2311944581
+"""
+
+
+class Day4Test(unittest.TestCase):
+    def test_solve(self):
+        d = rs_aoc2021.Day4(DAY4_EXAMPLE)
+        self.assertEqual((d.part1(), d.part2()), (4512, 1924))
+        self.assertEqual(d.num_cards, 3)
+        self.assertEqual(d.calls[:3], [7, 4, 9])
+        self.assertEqual(d.play(2), (12, 4512))
+        self.assertIsNone(d.play(2, [7, 4]))
+
+    def test_errors(self):
+        d = rs_aoc2021.Day4(DAY4_EXAMPLE)
+        with self.assertRaises(IndexError):
+            d.play(3)
+        for text in ["7,x\n\n1 2\n", "1,2\n\n1 2\n3\n", "1,2\n\n1 1\n"]:
+            with self.assertRaises(ValueError):
+                rs_aoc2021.Day4(text)
+
+
+class Day15Test(unittest.TestCase):
+    def test_solve(self):
+        d = rs_aoc2021.Day15(DAY15_EXAMPLE)
+        self.assertEqual((d.part1(), d.part2()), (40, 315))
+        self.assertEqual(d.lowest_risk(), 40)
+        self.assertEqual(d.lowest_risk(tiles=5), 315)
+
+    def test_errors(self):
+        with self.assertRaises(ValueError):
+            rs_aoc2021.Day15(DAY15_EXAMPLE).lowest_risk(tiles=0)
+        for text in ["", "12\n3\n", "1x\n"]:
+            with self.assertRaises(ValueError):
+                rs_aoc2021.Day15(text)
+
+
+class Day16Test(unittest.TestCase):
+    def test_solve(self):
+        self.assertEqual(rs_aoc2021.Day16("8A004A801A8002F478").part1(), 16)
+        self.assertEqual(rs_aoc2021.Day16("9C0141080250320F1802104A08").part2(), 1)
+        d = rs_aoc2021.Day16("D2FE28")
+        self.assertEqual(d.decode(), (2021, 21, 6))
+        self.assertEqual(d.to_bits()[:4], [True, True, False, True])
+
+    def test_errors(self):
+        for text in ["XYZ", "d2fe28"]:
+            with self.assertRaises(ValueError):
+                rs_aoc2021.Day16(text)
+        # Sums nested 100000 deep, each with one sub-packet, around a literal.
+        bits = "000000100000000001" * 100000 + "00010000001"
+        bits += "0" * (-len(bits) % 4)
+        deep = "".join("%X" % int(bits[i:i + 4], 2) for i in range(0, len(bits), 4))
+        for text in ["", "D2FE", "0000000", deep]:
+            d = rs_aoc2021.Day16(text)
+            for method in [d.decode, d.part1, d.part2]:
+                with self.assertRaises(ValueError):
+                    method()
+
+
+class Day18Test(unittest.TestCase):
+    def test_solve(self):
+        d = rs_aoc2021.Day18("[[[[4,3],4],4],[7,[[8,4],9]]]\n[1,1]\n")
+        self.assertEqual(d.numbers, ["[[[[4,3],4],4],[7,[[8,4],9]]]", "[1,1]"])
+        self.assertEqual(d.sum(), "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]")
+        self.assertEqual(d.part1(), 1384)
+        self.assertEqual(rs_aoc2021.snailfish_add("[1,2]", "[[3,4],5]"), "[[1,2],[[3,4],5]]")
+        self.assertEqual(rs_aoc2021.snailfish_magnitude("[[1,2],[[3,4],5]]"), 143)
+
+    def test_errors(self):
+        for text in ["", "[1", "[1,2]]", "[12,3]", "[a,b]"]:
+            with self.assertRaises(ValueError):
+                rs_aoc2021.snailfish_magnitude(text)
+            with self.assertRaises(ValueError):
+                rs_aoc2021.snailfish_add("[1,2]", text)
+        with self.assertRaises(ValueError):
+            rs_aoc2021.Day18("[1,2]\n[3\n")
+
+
+if __name__ == "__main__":
+    unittest.main()